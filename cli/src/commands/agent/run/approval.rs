@@ -0,0 +1,160 @@
+use crate::commands::agent::run::tui::{send_input_event, send_tool_call};
+use stakpak_shared::guardrails::{GuardrailAction, Guardrails};
+use stakpak_shared::models::integrations::openai::{ChatMessage, ToolCall, ToolCallResult};
+use stakpak_shared::policy::{ApprovalPolicy, PolicyAction, PolicyRule};
+use stakpak_tui::{AllowScope, Color, InputEvent, OutputEvent};
+use tokio::sync::mpsc::{Sender, WeakSender};
+
+/// Combine the policy from ~/.stakpak/config.toml with the project's .stakpak/policy.toml,
+/// returns the errors of the parts that couldn't be loaded
+pub fn load_approval_policy(user_policy: &ApprovalPolicy) -> (ApprovalPolicy, Vec<String>) {
+    let mut errors = Vec::new();
+
+    let mut policy = match user_policy.validate() {
+        Ok(()) => user_policy.clone(),
+        Err(e) => {
+            errors.push(format!(
+                "Ignoring the policy in ~/.stakpak/config.toml: {}",
+                e
+            ));
+            ApprovalPolicy::default()
+        }
+    };
+    match ApprovalPolicy::load(&ApprovalPolicy::project_path()) {
+        Ok(project_policy) => policy.merge(project_policy),
+        Err(e) => errors.push(format!("Ignoring the project policy: {}", e)),
+    }
+
+    (policy, errors)
+}
+
+/// Show a tool call to the user for confirmation, unless the approval policy allows or denies
+/// it. Decided tool calls are sent back to the agent loop as if the user had answered.
+/// Commands the guardrails escalate are always confirmed, whatever the policy says.
+pub async fn surface_tool_call(
+    input_tx: &Sender<InputEvent>,
+    output_tx: &WeakSender<OutputEvent>,
+    policy: &ApprovalPolicy,
+    guardrails: &Guardrails,
+    tool_call: &ToolCall,
+) -> Result<(), String> {
+    let arguments: serde_json::Value =
+        serde_json::from_str(&tool_call.function.arguments).unwrap_or_default();
+    let mut decision = policy.evaluate(&tool_call.function.name, &arguments);
+
+    // A shell runs its stdin as a script, and environment values can end up as code
+    let work_dir = arguments
+        .get("work_dir")
+        .and_then(|work_dir| work_dir.as_str());
+    let env_values = arguments
+        .get("env")
+        .and_then(|env| env.as_object())
+        .into_iter()
+        .flat_map(|env| env.values());
+    let violation = [arguments.get("command"), arguments.get("stdin")]
+        .into_iter()
+        .flatten()
+        .chain(env_values)
+        .filter_map(|input| input.as_str())
+        .filter_map(|input| guardrails.check(input, work_dir))
+        .find(|violation| violation.action == GuardrailAction::Escalate);
+    if let Some(violation) = violation.filter(|_| decision.action != PolicyAction::Deny) {
+        decision.action = PolicyAction::Ask;
+        send_input_event(
+            input_tx,
+            InputEvent::InputSubmittedWithColor(
+                format!(
+                    "Needs confirmation, guardrail rule {}: {}",
+                    violation.rule_id, violation.description
+                ),
+                Color::Yellow,
+            ),
+        )
+        .await?;
+    }
+    let rule = decision
+        .rule
+        .as_ref()
+        .map(PolicyRule::describe)
+        .unwrap_or_default();
+
+    let event = match decision.action {
+        PolicyAction::Ask => return send_tool_call(input_tx, tool_call).await,
+        PolicyAction::Allow => {
+            send_input_event(
+                input_tx,
+                InputEvent::InputSubmittedWithColor(
+                    format!(
+                        "Running {} without confirmation, allowed by policy rule: {}",
+                        tool_call.function.name, rule
+                    ),
+                    Color::DarkGray,
+                ),
+            )
+            .await?;
+            OutputEvent::AcceptTool(tool_call.clone())
+        }
+        PolicyAction::Deny => {
+            send_input_event(
+                input_tx,
+                InputEvent::InputSubmittedWithColor(
+                    format!(
+                        "Rejected {}, denied by policy rule: {}",
+                        tool_call.function.name, rule
+                    ),
+                    Color::Red,
+                ),
+            )
+            .await?;
+            OutputEvent::SendToolResult(ToolCallResult {
+                call: tool_call.clone(),
+                result: format!(
+                    "BLOCKED_BY_POLICY: The user's approval policy denies this tool call (rule: {}). Do not retry it, use another approach or ask the user.",
+                    rule
+                ),
+            })
+        }
+    };
+
+    // The TUI owns the output channel, if it's gone the session is over
+    match output_tx.upgrade() {
+        Some(output_tx) => output_tx.send(event).await.map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Add a rule allowing the tool call to the project policy
+pub fn always_allow(
+    policy: &mut ApprovalPolicy,
+    tool_call: &ToolCall,
+    scope: AllowScope,
+) -> Result<PolicyRule, String> {
+    let rule = match scope {
+        AllowScope::ToolCall => {
+            let arguments: serde_json::Value =
+                serde_json::from_str(&tool_call.function.arguments).unwrap_or_default();
+            PolicyRule::allow_tool_call(&tool_call.function.name, &arguments)
+        }
+        AllowScope::Tool => PolicyRule::allow_tool(&tool_call.function.name),
+    };
+    policy.add_rule(&ApprovalPolicy::project_path(), rule.clone())?;
+    Ok(rule)
+}
+
+/// Note for the agent when the user edited the tool call's arguments in the confirmation dialog
+/// before accepting it, so it doesn't assume its own proposal was applied
+pub fn user_edit_note(messages: &[ChatMessage], tool_call: &ToolCall) -> Option<String> {
+    let proposed = messages
+        .iter()
+        .rev()
+        .filter_map(|message| message.tool_calls.as_ref())
+        .flatten()
+        .find(|call| call.id == tool_call.id)?;
+    if proposed.function.arguments == tool_call.function.arguments {
+        return None;
+    }
+    Some(format!(
+        "Note: the user edited the content of this {} call before accepting it, the arguments that were applied are: {}",
+        tool_call.function.name, tool_call.function.arguments
+    ))
+}
@@ -1,6 +1,7 @@
 use crate::commands::agent::run::tui::send_input_event;
 use stakpak_api::Client;
 use stakpak_api::models::AgentOutput;
+use stakpak_shared::edit_journal::EditJournal;
 use stakpak_shared::models::integrations::openai::{
     ChatMessage, MessageContent, Role, ToolCall, ToolCallResult,
 };
@@ -122,6 +123,17 @@ pub async fn extract_checkpoint_messages_and_tool_calls(
     ))
 }
 
+/// Mark the checkpoint of the latest message in the edit journal, so the file edits made after
+/// it can be reverted with `stakpak edits revert`
+pub fn mark_checkpoint_in_edit_journal(messages: &[ChatMessage]) {
+    if let Some(checkpoint_id) = extract_checkpoint_id_from_messages(messages) {
+        let _ = EditJournal::update_session(|journal| {
+            journal.mark_checkpoint(&checkpoint_id);
+            Ok(())
+        });
+    }
+}
+
 pub fn extract_checkpoint_id_from_messages(messages: &[ChatMessage]) -> Option<String> {
     let checkpoint_id = messages
         .last()
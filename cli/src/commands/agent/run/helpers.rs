@@ -1,7 +1,7 @@
 use crate::utils::local_context::LocalContext;
 use stakpak_api::ListRuleBook;
 use stakpak_shared::models::integrations::openai::{
-    ChatMessage, FunctionDefinition, MessageContent, Role, Tool, ToolCallResult,
+    ChatMessage, FunctionDefinition, MessageContent, Role, Tool, ToolCall, ToolCallResult,
 };
 
 pub fn convert_tools_map(
@@ -42,6 +42,54 @@ pub fn tool_result(tool_call_id: String, result: String) -> ChatMessage {
     }
 }
 
+/// A run_command call running in a PTY sized to `terminal_size`, unless the agent chose a size,
+/// so the output streamed to the TUI fits its blocks
+pub fn with_pty_terminal_size(tool_call: &ToolCall, terminal_size: Option<[u16; 2]>) -> ToolCall {
+    let mut tool_call = tool_call.clone();
+    if tool_call.function.name != "run_command" {
+        return tool_call;
+    }
+    let Some(terminal_size) = terminal_size else {
+        return tool_call;
+    };
+    if let Ok(serde_json::Value::Object(mut arguments)) =
+        serde_json::from_str::<serde_json::Value>(&tool_call.function.arguments)
+    {
+        let pty = arguments.get("pty").and_then(|pty| pty.as_bool()) == Some(true);
+        if pty
+            && arguments
+                .get("terminal_size")
+                .is_none_or(|size| size.is_null())
+        {
+            arguments.insert(
+                "terminal_size".to_string(),
+                serde_json::json!(terminal_size),
+            );
+            tool_call.function.arguments = serde_json::Value::Object(arguments).to_string();
+        }
+    }
+    tool_call
+}
+
+/// Text of a tool call result. Contents meant only for the assistant, like the structured
+/// result of run_command, are left out when the result is shown to the user.
+pub fn tool_result_text(result: &rmcp::model::CallToolResult, for_user: bool) -> String {
+    result
+        .content
+        .iter()
+        .filter(|c| {
+            !for_user
+                || c.audience()
+                    .is_none_or(|audience| audience.contains(&rmcp::model::Role::User))
+        })
+        .map(|c| match c.raw.as_text() {
+            Some(text) => text.text.clone(),
+            None => String::new(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 pub fn add_local_context<'a>(
     messages: &'a [ChatMessage],
     user_input: &'a str,
@@ -1,3 +1,4 @@
+pub mod approval;
 pub mod checkpoint;
 pub mod helpers;
 pub mod mode_async;
@@ -1,23 +1,33 @@
+use crate::commands::agent::run::approval::{
+    always_allow, load_approval_policy, surface_tool_call, user_edit_note,
+};
 use crate::commands::agent::run::checkpoint::{
     extract_checkpoint_id_from_messages, extract_checkpoint_messages_and_tool_calls,
-    get_checkpoint_messages, get_messages_from_checkpoint_output,
+    get_checkpoint_messages, get_messages_from_checkpoint_output, mark_checkpoint_in_edit_journal,
 };
 use crate::commands::agent::run::helpers::{
     add_local_context, add_rulebooks, convert_tools_map, tool_call_history_string, tool_result,
-    user_message,
+    tool_result_text, user_message, with_pty_terminal_size,
 };
 use crate::commands::agent::run::stream::process_responses_stream;
 use crate::commands::agent::run::tooling::{list_sessions, run_tool_call};
-use crate::commands::agent::run::tui::{send_input_event, send_tool_call};
+use crate::commands::agent::run::tui::send_input_event;
 use crate::config::AppConfig;
 use crate::utils::check_update::get_latest_cli_version;
 use crate::utils::local_context::LocalContext;
 use crate::utils::network;
 use stakpak_api::{Client, ClientConfig, ListRuleBook};
 use stakpak_mcp_client::ClientManager;
-use stakpak_mcp_server::{MCPServerConfig, ToolMode};
+use stakpak_mcp_server::{
+    HttpAuth, MCPServerConfig, McpTransport, ToolFilter, ToolMode, generate_auth_token,
+};
+use stakpak_shared::edit_journal::EditJournal;
+use stakpak_shared::guardrails::Guardrails;
 use stakpak_shared::models::integrations::openai::{ChatMessage, ToolCall};
+use stakpak_shared::policy::ApprovalPolicy;
+use stakpak_shared::workspace::WorkspaceRoots;
 use stakpak_tui::{Color, InputEvent, OutputEvent};
+use std::time::Duration;
 use uuid::Uuid;
 
 pub struct RunInteractiveConfig {
@@ -25,6 +35,9 @@ pub struct RunInteractiveConfig {
     pub local_context: Option<LocalContext>,
     pub redact_secrets: bool,
     pub rulebooks: Option<Vec<ListRuleBook>>,
+    pub command_timeout: Option<Duration>,
+    pub allowed_paths: Vec<String>,
+    pub tool_filter: ToolFilter,
 }
 
 pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Result<(), String> {
@@ -32,11 +45,18 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
     let mut tools_queue: Vec<ToolCall> = Vec::new();
     let (input_tx, input_rx) = tokio::sync::mpsc::channel::<InputEvent>(100);
     let (output_tx, mut output_rx) = tokio::sync::mpsc::channel::<OutputEvent>(100);
+    // Lets the agent loop answer tool calls decided by the approval policy, without keeping
+    // the channel open once the TUI is gone
+    let policy_output_tx = output_tx.downgrade();
     let (mcp_progress_tx, mut mcp_progress_rx) = tokio::sync::mpsc::channel(100);
     let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
     let ctx_clone = ctx.clone();
     let bind_address = network::find_available_bind_address_descending().await?;
     let local_mcp_server_host = format!("http://{}", bind_address);
+    let local_mcp_server_auth_token = generate_auth_token();
+    let server_auth_token = local_mcp_server_auth_token.clone();
+    let allowed_paths = config.allowed_paths.clone();
+    let tool_filter = config.tool_filter.clone();
 
     // Spawn MCP server task
     let mcp_handle = tokio::spawn(async move {
@@ -47,8 +67,17 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                     api_endpoint: ctx_clone.api_endpoint.clone(),
                 },
                 redact_secrets: config.redact_secrets,
+                transport: McpTransport::Http,
                 bind_address,
+                http_auth: HttpAuth {
+                    token: Some(server_auth_token),
+                    allowed_origins: Vec::new(),
+                },
                 tool_mode: ToolMode::Combined,
+                command_timeout: config.command_timeout,
+                allowed_paths: config.allowed_paths,
+                tool_filter: config.tool_filter,
+                user_confirms_tool_calls: true,
             },
             Some(shutdown_rx),
         )
@@ -56,8 +85,16 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
     });
 
     // Initialize clients and tools
+    let (mcp_server_host, mcp_server_auth_token) = match ctx.mcp_server_host {
+        Some(mcp_server_host) => (mcp_server_host, ctx.mcp_server_auth_token),
+        None => (local_mcp_server_host, Some(local_mcp_server_auth_token)),
+    };
     let clients = ClientManager::new(
-        ctx.mcp_server_host.unwrap_or(local_mcp_server_host),
+        mcp_server_host,
+        mcp_server_auth_token,
+        &ctx.mcp_servers,
+        config.redact_secrets,
+        tool_filter,
         Some(mcp_progress_tx),
     )
     .await
@@ -92,6 +129,25 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
             let data = client.get_my_account().await?;
             send_input_event(&input_tx, InputEvent::GetStatus(data.to_text())).await?;
 
+            for error in clients.connection_errors() {
+                send_input_event(&input_tx, InputEvent::Error(error.clone())).await?;
+            }
+            let (mut policy, policy_errors) = load_approval_policy(&ctx.policy);
+            for error in policy_errors {
+                send_input_event(&input_tx, InputEvent::Error(error)).await?;
+            }
+            let workdir = std::env::current_dir().map_err(|e| e.to_string())?;
+            let (guardrails, guardrails_error) = Guardrails::load(&workdir);
+            // The MCP server already warned about allowed paths that don't exist
+            let (workspace, _) = WorkspaceRoots::new(&workdir, &allowed_paths);
+            if let Some(error) = guardrails_error {
+                send_input_event(
+                    &input_tx,
+                    InputEvent::Error(format!("Using the built-in guardrails only: {}", error)),
+                )
+                .await?;
+            }
+
             if let Some(checkpoint_id) = config.checkpoint_id {
                 let checkpoint_messages = get_checkpoint_messages(&client, &checkpoint_id).await?;
 
@@ -106,10 +162,18 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
 
                 if !tools_queue.is_empty() {
                     let initial_tool_call = tools_queue.remove(0);
-                    send_tool_call(&input_tx, &initial_tool_call).await?;
+                    surface_tool_call(
+                        &input_tx,
+                        &policy_output_tx,
+                        &policy,
+                        &guardrails,
+                        &initial_tool_call,
+                    )
+                    .await?;
                 }
 
                 messages.extend(chat_messages);
+                mark_checkpoint_in_edit_journal(&messages);
             }
 
             while let Some(output_event) = output_rx.recv().await {
@@ -157,17 +221,15 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                     }
                     OutputEvent::AcceptTool(tool_call) => {
                         send_input_event(&input_tx, InputEvent::Loading(true)).await?;
-                        let result = run_tool_call(&clients, &tools_map, &tool_call).await?;
+                        let sized_call =
+                            with_pty_terminal_size(&tool_call, stakpak_tui::pty_terminal_size());
+                        let result = run_tool_call(&clients, &tools_map, &sized_call).await?;
                         if let Some(result) = result {
-                            let result_content = result
-                                .content
-                                .iter()
-                                .map(|c| match c.raw.as_text() {
-                                    Some(text) => text.text.clone(),
-                                    None => String::new(),
-                                })
-                                .collect::<Vec<String>>()
-                                .join("\n");
+                            let result_content = tool_result_text(&result, false);
+                            let result_content = match user_edit_note(&messages, &tool_call) {
+                                Some(note) => format!("{}\n\n{}", result_content, note),
+                                None => result_content,
+                            };
 
                             messages
                                 .push(tool_result(tool_call.clone().id, result_content.clone()));
@@ -177,7 +239,7 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                                 InputEvent::ToolResult(
                                     stakpak_shared::models::integrations::openai::ToolCallResult {
                                         call: tool_call.clone(),
-                                        result: result_content,
+                                        result: tool_result_text(&result, true),
                                     },
                                 ),
                             )
@@ -187,14 +249,50 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
 
                         if !tools_queue.is_empty() {
                             let tool_call = tools_queue.remove(0);
-                            send_tool_call(&input_tx, &tool_call).await?;
+                            surface_tool_call(
+                                &input_tx,
+                                &policy_output_tx,
+                                &policy,
+                                &guardrails,
+                                &tool_call,
+                            )
+                            .await?;
                             continue;
                         }
                     }
+                    OutputEvent::AlwaysAllowTool(tool_call, scope) => {
+                        let message = match always_allow(&mut policy, &tool_call, scope) {
+                            Ok(rule) => InputEvent::InputSubmittedWithColor(
+                                format!(
+                                    "Added policy rule to {}: {}",
+                                    ApprovalPolicy::project_path().display(),
+                                    rule.describe()
+                                ),
+                                Color::DarkGray,
+                            ),
+                            Err(e) => InputEvent::Error(e),
+                        };
+                        send_input_event(&input_tx, message).await?;
+
+                        if let Some(output_tx) = policy_output_tx.upgrade() {
+                            output_tx
+                                .send(OutputEvent::AcceptTool(tool_call))
+                                .await
+                                .map_err(|e| e.to_string())?;
+                        }
+                        continue;
+                    }
                     OutputEvent::RejectTool(_tool_call) => {
                         if !tools_queue.is_empty() {
                             let tool_call = tools_queue.remove(0);
-                            send_tool_call(&input_tx, &tool_call).await?;
+                            surface_tool_call(
+                                &input_tx,
+                                &policy_output_tx,
+                                &policy,
+                                &guardrails,
+                                &tool_call,
+                            )
+                            .await?;
                         }
                         continue;
                     }
@@ -223,11 +321,19 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                                     )
                                     .await?;
                                 messages.extend(chat_messages);
+                                mark_checkpoint_in_edit_journal(&messages);
 
                                 tools_queue.extend(tool_calls.clone());
                                 if !tools_queue.is_empty() {
                                     let initial_tool_call = tools_queue.remove(0);
-                                    send_tool_call(&input_tx, &initial_tool_call).await?;
+                                    surface_tool_call(
+                                        &input_tx,
+                                        &policy_output_tx,
+                                        &policy,
+                                        &guardrails,
+                                        &initial_tool_call,
+                                    )
+                                    .await?;
                                 }
                                 send_input_event(&input_tx, InputEvent::Loading(false)).await?;
                             }
@@ -249,10 +355,30 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
 
                         if !tools_queue.is_empty() {
                             let tool_call = tools_queue.remove(0);
-                            send_tool_call(&input_tx, &tool_call).await?;
+                            surface_tool_call(
+                                &input_tx,
+                                &policy_output_tx,
+                                &policy,
+                                &guardrails,
+                                &tool_call,
+                            )
+                            .await?;
                             continue;
                         }
                     }
+                    OutputEvent::UndoLastEdit => {
+                        let undone =
+                            EditJournal::update_session(|journal| journal.undo_last(&workspace));
+                        let event = match undone {
+                            Ok(edit) => InputEvent::InputSubmittedWithColor(
+                                format!("Reverted the {} of {}", edit.tool, edit.path.display()),
+                                Color::DarkGray,
+                            ),
+                            Err(e) => InputEvent::Error(e),
+                        };
+                        send_input_event(&input_tx, event).await?;
+                        continue;
+                    }
                     OutputEvent::Memorize => {
                         let checkpoint_id = extract_checkpoint_id_from_messages(&messages);
                         if let Some(checkpoint_id) = checkpoint_id {
@@ -284,6 +410,7 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                 };
 
                 messages.push(response.choices[0].message.clone());
+                mark_checkpoint_in_edit_journal(&messages);
 
                 send_input_event(&input_tx, InputEvent::Loading(false)).await?;
 
@@ -292,7 +419,14 @@ pub async fn run_interactive(ctx: AppConfig, config: RunInteractiveConfig) -> Re
                     tools_queue.extend(tool_calls.clone());
                     if !tools_queue.is_empty() {
                         let tool_call = tools_queue.remove(0);
-                        send_tool_call(&input_tx, &tool_call).await?;
+                        surface_tool_call(
+                            &input_tx,
+                            &policy_output_tx,
+                            &policy,
+                            &guardrails,
+                            &tool_call,
+                        )
+                        .await?;
                         continue;
                     }
                 }
@@ -1,6 +1,9 @@
-use crate::commands::agent::run::checkpoint::get_checkpoint_messages;
+use crate::commands::agent::run::checkpoint::{
+    get_checkpoint_messages, mark_checkpoint_in_edit_journal,
+};
 use crate::commands::agent::run::helpers::{
-    add_local_context, add_rulebooks, convert_tools_map, tool_result, user_message,
+    add_local_context, add_rulebooks, convert_tools_map, tool_result, tool_result_text,
+    user_message,
 };
 use crate::commands::agent::run::tooling::run_tool_call;
 use crate::config::AppConfig;
@@ -8,8 +11,11 @@ use crate::utils::local_context::LocalContext;
 use crate::utils::network;
 use stakpak_api::{Client, ClientConfig, ListRuleBook};
 use stakpak_mcp_client::ClientManager;
-use stakpak_mcp_server::{MCPServerConfig, ToolMode};
+use stakpak_mcp_server::{
+    HttpAuth, MCPServerConfig, McpTransport, ToolFilter, ToolMode, generate_auth_token,
+};
 use stakpak_shared::models::integrations::openai::ChatMessage;
+use std::time::Duration;
 
 pub struct RunNonInteractiveConfig {
     pub prompt: String,
@@ -19,6 +25,9 @@ pub struct RunNonInteractiveConfig {
     pub local_context: Option<LocalContext>,
     pub redact_secrets: bool,
     pub rulebooks: Option<Vec<ListRuleBook>>,
+    pub command_timeout: Option<Duration>,
+    pub allowed_paths: Vec<String>,
+    pub tool_filter: ToolFilter,
 }
 
 pub async fn run_non_interactive(
@@ -30,6 +39,9 @@ pub async fn run_non_interactive(
     let ctx_clone = ctx.clone();
     let bind_address = network::find_available_bind_address_descending().await?;
     let local_mcp_server_host = format!("http://{}", bind_address);
+    let local_mcp_server_auth_token = generate_auth_token();
+    let server_auth_token = local_mcp_server_auth_token.clone();
+    let tool_filter = config.tool_filter.clone();
 
     tokio::spawn(async move {
         let _ = stakpak_mcp_server::start_server(
@@ -39,17 +51,40 @@ pub async fn run_non_interactive(
                     api_endpoint: ctx_clone.api_endpoint.clone(),
                 },
                 redact_secrets: config.redact_secrets,
+                transport: McpTransport::Http,
                 bind_address,
+                http_auth: HttpAuth {
+                    token: Some(server_auth_token),
+                    allowed_origins: Vec::new(),
+                },
                 tool_mode: ToolMode::Combined,
+                command_timeout: config.command_timeout,
+                allowed_paths: config.allowed_paths,
+                tool_filter: config.tool_filter,
+                user_confirms_tool_calls: false,
             },
             None,
         )
         .await;
     });
 
-    let clients = ClientManager::new(ctx.mcp_server_host.unwrap_or(local_mcp_server_host), None)
-        .await
-        .map_err(|e| e.to_string())?;
+    let (mcp_server_host, mcp_server_auth_token) = match ctx.mcp_server_host {
+        Some(mcp_server_host) => (mcp_server_host, ctx.mcp_server_auth_token),
+        None => (local_mcp_server_host, Some(local_mcp_server_auth_token)),
+    };
+    let clients = ClientManager::new(
+        mcp_server_host,
+        mcp_server_auth_token,
+        &ctx.mcp_servers,
+        config.redact_secrets,
+        tool_filter,
+        None,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    for error in clients.connection_errors() {
+        eprintln!("{}", error);
+    }
     let tools_map = clients.get_tools().await.map_err(|e| e.to_string())?;
     let tools = convert_tools_map(&tools_map);
 
@@ -82,6 +117,7 @@ pub async fn run_non_interactive(
             }
         }
         chat_messages.extend(checkpoint_messages);
+        mark_checkpoint_in_edit_journal(&chat_messages);
     }
 
     if let Some(message) = chat_messages.last() {
@@ -98,15 +134,7 @@ pub async fn run_non_interactive(
                         );
                     }
 
-                    let result_content = result
-                        .content
-                        .iter()
-                        .map(|c| match c.raw.as_text() {
-                            Some(text) => text.text.clone(),
-                            None => String::new(),
-                        })
-                        .collect::<Vec<String>>()
-                        .join("\n");
+                    let result_content = tool_result_text(&result, false);
 
                     chat_messages.push(tool_result(tool_call.id.clone(), result_content.clone()));
                 }
@@ -128,6 +156,7 @@ pub async fn run_non_interactive(
         .map_err(|e| e.to_string())?;
 
     chat_messages.push(response.choices[0].message.clone());
+    mark_checkpoint_in_edit_journal(&chat_messages);
 
     match config.verbose {
         true => {
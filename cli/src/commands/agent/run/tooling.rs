@@ -2,6 +2,7 @@ use rmcp::model::{CallToolRequestParam, CallToolResult};
 use stakpak_api::Client;
 use stakpak_api::models::AgentSession;
 use stakpak_mcp_client::ClientManager;
+use stakpak_shared::edit_journal::EditJournal;
 use stakpak_shared::models::integrations::openai::ToolCall;
 use stakpak_tui::SessionInfo;
 
@@ -35,21 +36,24 @@ pub async fn run_tool_call(
         .map(|(name, _)| name.clone());
 
     if let Some(client_name) = client_name {
-        let client = client_manager
-            .get_client(&client_name)
-            .await
-            .map_err(|e| e.to_string())?;
-        let result = client
-            .call_tool(CallToolRequestParam {
-                name: tool_name.clone().into(),
-                arguments: Some(
-                    serde_json::from_str(&tool_call.function.arguments)
-                        .map_err(|e| e.to_string())?,
-                ),
-            })
+        let result = client_manager
+            .call_tool(
+                &client_name,
+                CallToolRequestParam {
+                    name: tool_name.clone().into(),
+                    arguments: Some(
+                        serde_json::from_str(&tool_call.function.arguments)
+                            .map_err(|e| e.to_string())?,
+                    ),
+                },
+            )
             .await
             .map_err(|e| e.to_string())?;
 
+        // Link the file edits the tool call made to it, the journal is only for undoing edits
+        // so failing to update it doesn't fail the tool call
+        let _ = EditJournal::update_session(|journal| Ok(journal.tag_tool_call(&tool_call.id)));
+
         return Ok(Some(result));
     }
 
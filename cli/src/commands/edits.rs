@@ -0,0 +1,50 @@
+use clap::Subcommand;
+use stakpak_shared::edit_journal::EditJournal;
+use stakpak_shared::workspace::WorkspaceRoots;
+
+#[derive(Subcommand, PartialEq)]
+pub enum EditsCommands {
+    /// List the file edits made by the agent session in the current directory
+    List,
+    /// Undo the most recent file edit made by the agent
+    Undo,
+    /// Revert every file edit the agent made after a checkpoint
+    Revert {
+        /// Checkpoint to go back to, as shown by `stakpak edits list`
+        checkpoint_id: String,
+    },
+}
+
+impl EditsCommands {
+    /// `allowed_paths` are the directories besides the current one that edits may be reverted in
+    pub fn run(self, allowed_paths: &[String]) -> Result<(), String> {
+        let workdir = std::env::current_dir().map_err(|e| e.to_string())?;
+        let (workspace, _) = WorkspaceRoots::new(&workdir, allowed_paths);
+        match self {
+            EditsCommands::List => {
+                let journal = EditJournal::load_session()?;
+                if journal.edits.is_empty() {
+                    println!("No file edits recorded in the current directory");
+                } else {
+                    println!("{}", journal.summary());
+                }
+            }
+            EditsCommands::Undo => {
+                let edit = EditJournal::update_session(|journal| journal.undo_last(&workspace))?;
+                println!("Reverted the {} of {}", edit.tool, edit.path.display());
+            }
+            EditsCommands::Revert { checkpoint_id } => {
+                let reverted = EditJournal::update_session(|journal| {
+                    journal.revert_to_checkpoint(&checkpoint_id, &workspace)
+                })?;
+                if reverted.is_empty() {
+                    println!("No edits to revert after checkpoint {}", checkpoint_id);
+                }
+                for edit in reverted {
+                    println!("Reverted the {} of {}", edit.tool, edit.path.display());
+                }
+            }
+        }
+        Ok(())
+    }
+}
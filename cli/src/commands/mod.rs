@@ -10,12 +10,18 @@ use stakpak_api::{
     Client, ClientConfig,
     models::{AgentID, Document, ProvisionerType, TranspileTargetProvisionerType},
 };
-use stakpak_mcp_server::{MCPServerConfig, ToolMode};
+use stakpak_mcp_server::{
+    HttpAuth, MCPServerConfig, McpTransport, ToolFilter, ToolMode, generate_auth_token,
+};
+use std::time::Duration;
 use termimad::MadSkin;
 use walkdir::WalkDir;
 
 pub mod agent;
+pub mod edits;
 pub mod flow;
+pub mod scan;
+pub mod secrets;
 pub mod warden;
 
 #[derive(Subcommand, PartialEq)]
@@ -148,15 +154,94 @@ pub enum Commands {
         #[arg(long, short = 'm', default_value_t = ToolMode::Combined)]
         tool_mode: ToolMode,
 
+        /// Transport to serve MCP over (stdio, http)
+        #[arg(long, default_value_t = McpTransport::Http)]
+        transport: McpTransport,
+
+        /// Address the HTTP transport listens on, like 0.0.0.0:8420 (default: a free local port)
+        #[arg(long)]
+        bind: Option<String>,
+
+        /// Bearer token HTTP clients must send (default: a token generated for this run)
+        #[arg(
+            long = "auth-token",
+            env = "STAKPAK_MCP_AUTH_TOKEN",
+            hide_env_values = true
+        )]
+        auth_token: Option<String>,
+
+        /// Origin browsers may call the HTTP transport from (repeatable, * allows any)
+        #[arg(long = "allowed-origin")]
+        allowed_origins: Vec<String>,
+
         /// Allow indexing of large projects (more than 500 supported files)
         #[arg(long = "index-big-project", default_value_t = false)]
         index_big_project: bool,
+
+        /// Default timeout in seconds for commands run through the run_command tool
+        #[arg(long = "command-timeout")]
+        command_timeout: Option<u64>,
+
+        /// Directory besides the working directory the file tools may access (repeatable)
+        #[arg(long = "allow-path")]
+        allow_paths: Vec<String>,
+
+        /// Tool profile to serve: readonly, no-shell or one of tool_profiles in the config
+        #[arg(long = "tool-profile")]
+        tool_profile: Option<String>,
+
+        /// Only serve these tools (comma separated)
+        #[arg(long = "tools", value_delimiter = ',')]
+        tools: Vec<String>,
+
+        /// Never serve these tools (comma separated)
+        #[arg(long = "exclude-tools", value_delimiter = ',')]
+        exclude_tools: Vec<String>,
     },
 
     /// Stakpak Agent (WARNING: These agents are in early alpha development and may be unstable)
     #[command(subcommand)]
     Agent(AgentCommands),
 
+    /// Manage the secrets redacted by the agent
+    #[command(subcommand)]
+    Secrets(secrets::SecretsCommands),
+
+    /// List, undo and revert the file edits made by the agent
+    #[command(subcommand)]
+    Edits(edits::EditsCommands),
+
+    /// Scan files, stdin or a git diff for secrets, exits with a non-zero code if any are found
+    Scan {
+        /// Files or directories to scan, files ignored by a directory's .gitignore are skipped
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+
+        /// Scan the content piped to stdin
+        #[arg(long, default_value_t = false, conflicts_with_all = ["git_diff", "staged"])]
+        stdin: bool,
+
+        /// Only scan the lines added since a git revision (defaults to HEAD)
+        #[arg(long = "git-diff", num_args = 0..=1, default_missing_value = "HEAD", conflicts_with = "staged")]
+        git_diff: Option<String>,
+
+        /// Only scan the lines added in staged changes, for use in pre-commit hooks
+        #[arg(long, default_value_t = false)]
+        staged: bool,
+
+        /// Output format (text, json, sarif)
+        #[arg(long, short, default_value_t = scan::ScanFormat::Text)]
+        format: scan::ScanFormat,
+
+        /// JSON report of an earlier scan, findings already in it are ignored
+        #[arg(long, short)]
+        baseline: Option<String>,
+
+        /// Write the report to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+
     /// Stakpak Warden wraps coding agents to apply security policies and limit their capabilities
     Warden {
         /// Environment variables to pass to container
@@ -171,13 +256,43 @@ pub enum Commands {
 }
 
 impl Commands {
+    /// Whether the command calls the Stakpak API, the others run without an API key
+    pub fn needs_api_key(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Version
+                | Commands::Scan { .. }
+                | Commands::Secrets(_)
+                | Commands::Edits(_)
+                | Commands::Mcp {
+                    tool_mode: ToolMode::LocalOnly,
+                    ..
+                }
+        )
+    }
+
     pub async fn run(self, config: AppConfig) -> Result<(), String> {
         match self {
             Commands::Mcp {
                 disable_secret_redaction,
                 tool_mode,
+                transport,
+                bind,
+                auth_token,
+                allowed_origins,
                 index_big_project,
+                command_timeout,
+                allow_paths,
+                tool_profile,
+                tools,
+                exclude_tools,
             } => {
+                let tool_filter = ToolFilter::new(
+                    tool_profile.as_deref(),
+                    &config.tool_profiles,
+                    &tools,
+                    &exclude_tools,
+                )?;
                 let api_config: ClientConfig = config.clone().into();
                 match tool_mode {
                     ToolMode::RemoteOnly | ToolMode::Combined => {
@@ -210,20 +325,79 @@ impl Commands {
                     ToolMode::LocalOnly => {}
                 }
 
-                let bind_address = network::find_available_bind_address_descending().await?;
-                println!("MCP server started at http://{}", bind_address);
+                // Over stdio, stdout carries the protocol and nothing else
+                let (bind_address, auth_token) = match transport {
+                    McpTransport::Http => {
+                        let bind_address = match bind {
+                            Some(bind_address) => bind_address,
+                            None => network::find_available_bind_address_descending().await?,
+                        };
+                        println!("MCP server started at http://{}/mcp", bind_address);
+                        let auth_token = match auth_token {
+                            Some(auth_token) if auth_token.trim().is_empty() => {
+                                return Err("--auth-token can't be empty".into());
+                            }
+                            Some(auth_token) => auth_token,
+                            None => {
+                                let auth_token = generate_auth_token();
+                                println!("Authorization: Bearer {}", auth_token);
+                                auth_token
+                            }
+                        };
+                        (bind_address, Some(auth_token))
+                    }
+                    McpTransport::Stdio => (String::new(), None),
+                };
                 stakpak_mcp_server::start_server(
                     MCPServerConfig {
                         api: config.into(),
                         redact_secrets: !disable_secret_redaction,
-                        bind_address: bind_address.clone(),
+                        transport,
+                        bind_address,
+                        http_auth: HttpAuth {
+                            token: auth_token,
+                            allowed_origins,
+                        },
                         tool_mode,
+                        command_timeout: command_timeout.map(Duration::from_secs),
+                        user_confirms_tool_calls: false,
+                        allowed_paths: [config.allowed_paths.clone(), allow_paths].concat(),
+                        tool_filter,
                     },
                     None,
                 )
                 .await
                 .map_err(|e| e.to_string())?;
             }
+            Commands::Scan {
+                paths,
+                stdin,
+                git_diff,
+                staged,
+                format,
+                baseline,
+                output,
+            } => {
+                let source = if stdin {
+                    scan::ScanSource::Stdin
+                } else if staged {
+                    scan::ScanSource::GitDiff(vec!["--cached".to_string()])
+                } else if let Some(revision) = git_diff {
+                    scan::ScanSource::GitDiff(vec![revision])
+                } else {
+                    scan::ScanSource::Paths(paths)
+                };
+
+                let findings = scan::run(scan::ScanOptions {
+                    source,
+                    format,
+                    baseline,
+                    output,
+                })?;
+                if findings > 0 {
+                    std::process::exit(1);
+                }
+            }
             Commands::Login { api_key } => {
                 let mut updated_config = config.clone();
                 updated_config.api_key = Some(api_key);
@@ -546,6 +720,12 @@ impl Commands {
 
                 println!("[Saved checkpoint ID to .stakpak_apply_checkpoint]");
             }
+            Commands::Secrets(secrets_command) => {
+                secrets_command.run()?;
+            }
+            Commands::Edits(edits_command) => {
+                edits_command.run(&config.allowed_paths)?;
+            }
             Commands::Warden {
                 env,
                 volume,
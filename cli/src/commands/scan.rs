@@ -0,0 +1,186 @@
+use stakpak_shared::secrets::gitleaks::{GitleaksConfig, load_gitleaks_config, user_config_paths};
+use stakpak_shared::secrets::scan::{
+    Baseline, Finding, scan_content, scan_diff, to_json, to_sarif,
+};
+use stakpak_shared::utils::{matches_gitignore_pattern, read_gitignore_patterns};
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Name used for findings in content read from stdin
+const STDIN_FILE: &str = "<stdin>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanFormat {
+    Text,
+    Json,
+    Sarif,
+}
+
+impl std::fmt::Display for ScanFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ScanFormat::Text => "text",
+            ScanFormat::Json => "json",
+            ScanFormat::Sarif => "sarif",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::str::FromStr for ScanFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ScanFormat::Text),
+            "json" => Ok(ScanFormat::Json),
+            "sarif" => Ok(ScanFormat::Sarif),
+            _ => Err(format!("Invalid output format: {}", s)),
+        }
+    }
+}
+
+/// Where the content to scan comes from
+pub enum ScanSource {
+    Paths(Vec<String>),
+    Stdin,
+    /// `git diff` arguments, only the added lines are scanned
+    GitDiff(Vec<String>),
+}
+
+pub struct ScanOptions {
+    pub source: ScanSource,
+    pub format: ScanFormat,
+    pub baseline: Option<String>,
+    pub output: Option<String>,
+}
+
+/// Run the scan and print the report, returns the number of findings not in the baseline
+pub fn run(options: ScanOptions) -> Result<usize, String> {
+    let (config, errors) = load_gitleaks_config(&user_config_paths());
+    for error in &errors.config_errors {
+        eprintln!("Warning: {}", error);
+    }
+
+    let mut findings = match &options.source {
+        ScanSource::Paths(paths) => scan_paths(paths, &config)?,
+        ScanSource::Stdin => {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+            scan_content(&content, STDIN_FILE, &config)
+        }
+        ScanSource::GitDiff(args) => scan_diff(&git_diff(args)?, &config),
+    };
+
+    let total = findings.len();
+    if let Some(baseline_path) = &options.baseline {
+        let content = std::fs::read_to_string(baseline_path)
+            .map_err(|e| format!("Failed to read baseline {}: {}", baseline_path, e))?;
+        let baseline = Baseline::from_json(&content)?;
+        findings.retain(|finding| !baseline.contains(finding));
+    }
+
+    let report = match options.format {
+        ScanFormat::Text => to_text(&findings),
+        ScanFormat::Json => to_json(&findings)?,
+        ScanFormat::Sarif => to_sarif(&findings, env!("CARGO_PKG_VERSION"))?,
+    };
+    match &options.output {
+        Some(output) => std::fs::write(output, format!("{}\n", report))
+            .map_err(|e| format!("Failed to write report to {}: {}", output, e))?,
+        None if report.is_empty() => {}
+        None => println!("{}", report),
+    }
+
+    // The summary goes to stderr so JSON and SARIF reports can be piped
+    let ignored = total - findings.len();
+    let ignored = if ignored > 0 {
+        format!(" ({} ignored by baseline)", ignored)
+    } else {
+        String::new()
+    };
+    if findings.is_empty() {
+        eprintln!("No secrets found{}", ignored);
+    } else {
+        eprintln!("{} secrets found{}", findings.len(), ignored);
+    }
+
+    Ok(findings.len())
+}
+
+fn to_text(findings: &[Finding]) -> String {
+    findings
+        .iter()
+        .map(|finding| {
+            let mut text = format!(
+                "{}:{}:{}: {}",
+                finding.file, finding.line, finding.column, finding.rule_id
+            );
+            if !finding.description.is_empty() {
+                text.push_str(&format!("\n    {}", finding.description));
+            }
+            text.push_str(&format!("\n    Fingerprint: {}", finding.fingerprint));
+            text
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scan files and directories, skipping files ignored by the directory's .gitignore
+fn scan_paths(paths: &[String], config: &GitleaksConfig) -> Result<Vec<Finding>, String> {
+    let mut findings = Vec::new();
+    for path in paths {
+        if !Path::new(path).exists() {
+            return Err(format!("{} does not exist", path));
+        }
+
+        let ignore_patterns = read_gitignore_patterns(path);
+        for entry in WalkDir::new(path)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|e| {
+                let relative_path = e.path().strip_prefix(path).unwrap_or(e.path());
+                let relative_path = relative_path.to_string_lossy();
+                !ignore_patterns
+                    .iter()
+                    .any(|pattern| matches_gitignore_pattern(pattern, &relative_path))
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            // Binary files are decoded lossily: path rules (keystores, PKCS #12 bundles) flag
+            // them whatever their content, and text secrets inside them are still found
+            let content = match std::fs::read(entry.path()) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", entry.path().display(), e);
+                    continue;
+                }
+            };
+            let file = entry.path().to_string_lossy();
+            let file = file.strip_prefix("./").unwrap_or(&file);
+            findings.extend(scan_content(&content, file, config));
+        }
+    }
+    Ok(findings)
+}
+
+fn git_diff(args: &[String]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(["diff", "--no-color", "--no-ext-diff", "-U0"])
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git diff: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
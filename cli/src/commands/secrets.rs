@@ -0,0 +1,102 @@
+use clap::Subcommand;
+use stakpak_shared::secrets::encryption::EncryptionKey;
+use stakpak_shared::secrets::gitleaks::{load_gitleaks_config, user_config_paths};
+use stakpak_shared::secrets::redaction_store::RedactionStore;
+
+#[derive(Subcommand, PartialEq)]
+pub enum SecretsCommands {
+    /// Delete the redacted secrets stored by the agent session in the current directory
+    Purge {
+        /// Also delete the encryption key, leaving the secrets of every other session unreadable
+        #[arg(long, default_value_t = false)]
+        key: bool,
+    },
+    /// List the effective secret detection rules, including rules from ~/.stakpak/gitleaks.toml and .stakpak/gitleaks.toml
+    Rules {
+        /// Only list rules loaded from user config files
+        #[arg(long, default_value_t = false)]
+        user_only: bool,
+    },
+}
+
+impl SecretsCommands {
+    pub fn run(self) -> Result<(), String> {
+        match self {
+            SecretsCommands::Purge { key } => {
+                let removed = RedactionStore::purge_session()?;
+                if removed.is_empty() {
+                    println!("No session secrets found in the current directory");
+                }
+                for path in removed {
+                    println!("Removed {}", path.display());
+                }
+
+                if key {
+                    let key_path = EncryptionKey::default_path()?;
+                    match std::fs::remove_file(&key_path) {
+                        Ok(()) => println!("Removed {}", key_path.display()),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                            println!("No encryption key found at {}", key_path.display())
+                        }
+                        Err(e) => {
+                            return Err(format!("Failed to remove {}: {}", key_path.display(), e));
+                        }
+                    }
+                }
+            }
+            SecretsCommands::Rules { user_only } => {
+                let user_paths = user_config_paths();
+                let (config, errors) = load_gitleaks_config(&user_paths);
+
+                println!("Config files:");
+                println!("  (built-in rules)");
+                for path in &user_paths {
+                    let status = if path.exists() { "" } else { " (not found)" };
+                    println!("  {}{}", path.display(), status);
+                }
+                println!();
+
+                let rules = config
+                    .rules
+                    .iter()
+                    .filter(|rule| !user_only || rule.source.is_some())
+                    .collect::<Vec<_>>();
+                println!("{} rules:", rules.len());
+                for rule in rules {
+                    let source = rule
+                        .source
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or("built-in".to_string());
+                    println!("  {} [{}]", rule.id, source);
+                    if !rule.description.is_empty() {
+                        println!("      {}", rule.description);
+                    }
+                }
+
+                if let Some(allowlist) = &config.allowlist {
+                    println!();
+                    println!("Global allowlist:");
+                    println!(
+                        "  {} paths, {} regexes, {} stopwords",
+                        allowlist.paths.as_ref().map_or(0, |p| p.len()),
+                        allowlist.regexes.as_ref().map_or(0, |r| r.len()),
+                        allowlist.stopwords.as_ref().map_or(0, |s| s.len())
+                    );
+                }
+
+                if !errors.config_errors.is_empty() || !errors.regex_errors.is_empty() {
+                    println!();
+                    println!("Errors:");
+                    for error in &errors.config_errors {
+                        println!("  {}", error);
+                    }
+                    for (rule_id, error) in &errors.regex_errors {
+                        println!("  Rule {} was skipped: {}", rule_id, error);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
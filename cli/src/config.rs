@@ -1,6 +1,10 @@
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
 use stakpak_api::ClientConfig;
+use stakpak_mcp_client::McpServerConfig;
+use stakpak_mcp_server::ToolProfile;
+use stakpak_shared::policy::ApprovalPolicy;
+use std::collections::HashMap;
 use std::fs::{create_dir_all, write};
 use std::path::Path;
 
@@ -9,7 +13,23 @@ pub struct AppConfig {
     pub api_endpoint: String,
     pub api_key: Option<String>,
     pub mcp_server_host: Option<String>,
+    /// Bearer token sent to `mcp_server_host`, the agent's own server gets a generated one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp_server_auth_token: Option<String>,
     pub machine_name: Option<String>,
+    /// Tool approval rules for interactive mode, merged with the project's .stakpak/policy.toml
+    #[serde(default, skip_serializing_if = "ApprovalPolicy::is_empty")]
+    pub policy: ApprovalPolicy,
+    /// Directories besides the working directory the agent's file tools may access
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_paths: Vec<String>,
+    /// MCP servers the agent uses besides its own, by name. Their tools are prefixed with the
+    /// name, like `github__create_issue`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mcp_servers: HashMap<String, McpServerConfig>,
+    /// Named tool subsets for `--tool-profile`, a profile named like a built-in one replaces it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tool_profiles: HashMap<String, ToolProfile>,
 }
 
 impl From<AppConfig> for ClientConfig {
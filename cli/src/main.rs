@@ -1,7 +1,8 @@
 use clap::Parser;
 use names::{self, Name};
 use stakpak_api::{Client, ClientConfig};
-use std::{env, io::Write, path::Path};
+use stakpak_mcp_server::{McpTransport, ToolFilter};
+use std::{env, io::Write, path::Path, time::Duration};
 
 mod code_index;
 mod commands;
@@ -66,6 +67,26 @@ struct Cli {
     #[arg(long = "disable-official-rulebooks", default_value_t = false)]
     disable_official_rulebooks: bool,
 
+    /// Default timeout in seconds for commands run by the agent
+    #[arg(long = "command-timeout")]
+    command_timeout: Option<u64>,
+
+    /// Directory besides the working directory the agent's file tools may access (repeatable)
+    #[arg(long = "allow-path")]
+    allow_paths: Vec<String>,
+
+    /// Tool profile of the agent: readonly, no-shell or one of tool_profiles in the config
+    #[arg(long = "tool-profile")]
+    tool_profile: Option<String>,
+
+    /// Only give the agent these tools (comma separated)
+    #[arg(long = "tools", value_delimiter = ',')]
+    tools: Vec<String>,
+
+    /// Never give the agent these tools (comma separated)
+    #[arg(long = "exclude-tools", value_delimiter = ',')]
+    exclude_tools: Vec<String>,
+
     /// Prompt to run the agent with in non-interactive mode
     #[clap(required_if_eq("print", "true"))]
     prompt: Option<String>,
@@ -92,7 +113,8 @@ async fn main() {
                 tracing_subscriber::EnvFilter::try_from_default_env()
                     .unwrap_or_else(|_| format!("error,{}=debug", env!("CARGO_CRATE_NAME")).into()),
             )
-            .with(tracing_subscriber::fmt::layer())
+            // Off stdout, which carries the protocol of `stakpak mcp --transport stdio`
+            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
             .init();
     }
 
@@ -100,7 +122,24 @@ async fn main() {
         Ok(mut config) => {
             let mut config_updated = false;
 
-            if config.api_key.is_none() {
+            let needs_api_key = cli.command.as_ref().is_none_or(Commands::needs_api_key);
+            // A stdio MCP server's stdout carries the protocol and its stdin isn't a user, it
+            // can't prompt for the key
+            let stdio_mcp = matches!(
+                cli.command,
+                Some(Commands::Mcp {
+                    transport: McpTransport::Stdio,
+                    ..
+                })
+            );
+            if config.api_key.is_none() && needs_api_key && stdio_mcp {
+                eprintln!(
+                    "Stakpak API Key not found! Set STAKPAK_API_KEY, or run stakpak once to enter it"
+                );
+                std::process::exit(1);
+            }
+
+            if config.api_key.is_none() && needs_api_key {
                 println!();
                 println!("Stakpak API Key not found!");
                 println!(
@@ -143,7 +182,19 @@ async fn main() {
 
             match cli.command {
                 Some(command) => {
-                    let _ = check_update(format!("v{}", env!("CARGO_PKG_VERSION")).as_str()).await;
+                    // Scan reports can be piped and stdio MCP servers speak over stdout, keep it
+                    // free of the update notice
+                    if !matches!(
+                        command,
+                        Commands::Scan { .. }
+                            | Commands::Mcp {
+                                transport: McpTransport::Stdio,
+                                ..
+                            }
+                    ) {
+                        let _ =
+                            check_update(format!("v{}", env!("CARGO_PKG_VERSION")).as_str()).await;
+                    }
                     match command.run(config).await {
                         Ok(_) => {}
                         Err(e) => {
@@ -153,6 +204,18 @@ async fn main() {
                     }
                 }
                 None => {
+                    let tool_filter = match ToolFilter::new(
+                        cli.tool_profile.as_deref(),
+                        &config.tool_profiles,
+                        &cli.tools,
+                        &cli.exclude_tools,
+                    ) {
+                        Ok(tool_filter) => tool_filter,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
                     let local_context = analyze_local_context(&config).await.ok();
                     let api_config: ClientConfig = config.clone().into();
                     let client = if let Ok(client) = Client::new(&api_config) {
@@ -195,6 +258,8 @@ async fn main() {
                         }
                     }
 
+                    let allowed_paths = [config.allowed_paths.clone(), cli.allow_paths].concat();
+
                     match (cli.r#async, cli.print || cli.approve) {
                         // Async mode: run continuously until no more tool calls
                         (true, _) => match agent::run::run_async(
@@ -206,6 +271,9 @@ async fn main() {
                                 local_context,
                                 redact_secrets: !cli.disable_secret_redaction,
                                 rulebooks,
+                                command_timeout: cli.command_timeout.map(Duration::from_secs),
+                                allowed_paths,
+                                tool_filter,
                             },
                         )
                         .await
@@ -228,6 +296,9 @@ async fn main() {
                                 local_context,
                                 redact_secrets: !cli.disable_secret_redaction,
                                 rulebooks,
+                                command_timeout: cli.command_timeout.map(Duration::from_secs),
+                                allowed_paths,
+                                tool_filter,
                             },
                         )
                         .await
@@ -247,6 +318,9 @@ async fn main() {
                                 local_context,
                                 redact_secrets: !cli.disable_secret_redaction,
                                 rulebooks,
+                                command_timeout: cli.command_timeout.map(Duration::from_secs),
+                                allowed_paths,
+                                tool_filter,
                             },
                         )
                         .await
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use stakpak_shared::file_tree::{TreeOptions, format_file_size, read_tree};
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
@@ -25,7 +26,7 @@ pub struct LocalContext {
 pub struct FileInfo {
     pub is_directory: bool,
     pub size: Option<u64>,
-    pub children: Option<Vec<String>>,
+    pub entry_count: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -98,9 +99,9 @@ impl fmt::Display for LocalContext {
 
                 if info.is_directory {
                     write!(f, "{}/", name)?;
-                    if let Some(children) = &info.children {
-                        if !children.is_empty() {
-                            write!(f, " ({} items)", children.len())?;
+                    if let Some(entry_count) = info.entry_count {
+                        if entry_count > 0 {
+                            write!(f, " ({} items)", entry_count)?;
                         } else {
                             write!(f, " (empty)")?;
                         }
@@ -119,23 +120,6 @@ impl fmt::Display for LocalContext {
     }
 }
 
-fn format_file_size(size: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size_f = size as f64;
-    let mut unit_index = 0;
-
-    while size_f >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size_f /= 1024.0;
-        unit_index += 1;
-    }
-
-    if unit_index == 0 {
-        format!("{} {}", size, UNITS[unit_index])
-    } else {
-        format!("{:.1} {}", size_f, UNITS[unit_index])
-    }
-}
-
 pub async fn analyze_local_context(
     config: &AppConfig,
 ) -> Result<LocalContext, Box<dyn std::error::Error>> {
@@ -350,55 +334,35 @@ fn get_working_directory() -> Result<String, Box<dyn std::error::Error>> {
 fn get_file_structure(
     dir_path: &str,
 ) -> Result<HashMap<String, FileInfo>, Box<dyn std::error::Error>> {
-    let mut file_structure = HashMap::new();
     let path = Path::new(dir_path);
 
     if !path.exists() {
-        return Ok(file_structure);
-    }
-
-    // Read the current directory
-    let entries = fs::read_dir(path)?;
-
-    for entry in entries {
-        let entry = entry?;
-        let file_name = entry.file_name().to_string_lossy().to_string();
-        let file_path = entry.path();
-        let metadata = entry.metadata()?;
-
-        let is_directory = metadata.is_dir();
-        let size = if is_directory {
-            None
-        } else {
-            Some(metadata.len())
-        };
-
-        // For directories, get immediate children (non-recursive to avoid deep trees)
-        let children = if is_directory {
-            match fs::read_dir(&file_path) {
-                Ok(dir_entries) => {
-                    let child_names: Result<Vec<String>, _> = dir_entries
-                        .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
-                        .collect();
-                    child_names.ok()
-                }
-                Err(_) => None,
-            }
-        } else {
-            None
-        };
-
-        file_structure.insert(
-            file_name,
-            FileInfo {
-                is_directory,
-                size,
-                children,
-            },
-        );
+        return Ok(HashMap::new());
     }
 
-    Ok(file_structure)
+    // List the current directory, the entries of subdirectories are only counted
+    let options = TreeOptions {
+        depth: 1,
+        include_hidden: true,
+        respect_gitignore: false,
+        max_entries: usize::MAX,
+    };
+    let tree = read_tree(path, &options, |_| true)?;
+
+    Ok(tree
+        .children
+        .into_iter()
+        .map(|entry| {
+            (
+                entry.name,
+                FileInfo {
+                    is_directory: entry.is_directory,
+                    size: entry.size,
+                    entry_count: entry.entry_count,
+                },
+            )
+        })
+        .collect())
 }
 
 fn get_git_info(dir_path: &str) -> GitInfo {
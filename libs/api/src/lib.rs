@@ -142,12 +142,7 @@ impl Client {
     }
 
     pub async fn get_rulebook_by_uri(&self, uri: &str) -> Result<RuleBook, String> {
-        // URL encode the URI to handle special characters
-        let base_url = Url::parse(&format!("{}/rules/", self.base_url))
-            .map_err(|e| format!("Invalid base URL: {}", e))?;
-        let url = base_url
-            .join(uri)
-            .map_err(|e| format!("Failed to construct URL: {}", e))?;
+        let url = rulebook_url(&self.base_url, uri)?;
 
         let response = self
             .client
@@ -1137,3 +1132,55 @@ pub struct ToolsCallParams {
 pub struct ToolsCallResponse {
     pub content: Vec<Content>,
 }
+
+/// URL of a rulebook under the API's `rules/` path. Each segment of the URI is percent-encoded
+/// on its own, so a URI can't replace the host (`//evil.example/x`), climb out of `rules/`
+/// (`..`) or carry a scheme, which would send the API key elsewhere.
+fn rulebook_url(base_url: &str, uri: &str) -> Result<Url, String> {
+    let path = uri.strip_prefix("stakpak://").unwrap_or(uri);
+    let segments = path.split('/').collect::<Vec<_>>();
+    if segments.iter().any(|segment| {
+        segment.is_empty() || *segment == "." || *segment == ".." || segment.contains(':')
+    }) {
+        return Err(format!("Invalid rulebook URI '{}'", uri));
+    }
+
+    let mut url = Url::parse(&format!("{}/rules/", base_url))
+        .map_err(|e| format!("Invalid base URL: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid base URL: {}", base_url))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rulebook_url_stays_under_rules() {
+        let base_url = "https://apiv2.stakpak.dev/v1";
+        assert_eq!(
+            rulebook_url(base_url, "stakpak://acme/deploy guide.md")
+                .unwrap()
+                .as_str(),
+            "https://apiv2.stakpak.dev/v1/rules/acme/deploy%20guide.md"
+        );
+        assert_eq!(
+            rulebook_url(base_url, "stakpak://acme/a?b#c")
+                .unwrap()
+                .as_str(),
+            "https://apiv2.stakpak.dev/v1/rules/acme/a%3Fb%23c"
+        );
+        for uri in [
+            "stakpak:////evil.example/x",
+            "//evil.example/x",
+            "stakpak://acme/../../admin",
+            "https://evil.example/x",
+            "stakpak://",
+        ] {
+            assert!(rulebook_url(base_url, uri).is_err(), "{}", uri);
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use anyhow::Result;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
 use rmcp::{
     ClientHandler, RoleClient, ServiceExt,
     model::{ClientCapabilities, ClientInfo, Implementation},
     service::RunningService,
-    transport::StreamableHttpClientTransport,
+    transport::{
+        StreamableHttpClientTransport, streamable_http_client::StreamableHttpClientTransportConfig,
+    },
 };
 use stakpak_shared::models::integrations::openai::ToolCallResultProgress;
 use tokio::sync::mpsc::Sender;
@@ -13,6 +16,12 @@ pub struct LocalClientHandler {
     progress_tx: Option<Sender<ToolCallResultProgress>>,
 }
 
+impl LocalClientHandler {
+    pub(crate) fn new(progress_tx: Option<Sender<ToolCallResultProgress>>) -> Self {
+        Self { progress_tx }
+    }
+}
+
 impl ClientHandler for LocalClientHandler {
     async fn on_progress(
         &self,
@@ -47,11 +56,28 @@ impl ClientHandler for LocalClientHandler {
 
 pub async fn local_client(
     host: String,
+    auth_token: Option<String>,
     progress_tx: Option<Sender<ToolCallResultProgress>>,
 ) -> Result<RunningService<RoleClient, LocalClientHandler>> {
-    let transport = StreamableHttpClientTransport::from_uri(format!("{}/mcp", host));
+    let uri = format!("{}/mcp", host);
+    let transport = match auth_token {
+        Some(auth_token) => {
+            let mut headers = HeaderMap::new();
+            let mut authorization = HeaderValue::from_str(&format!("Bearer {}", auth_token))?;
+            authorization.set_sensitive(true);
+            headers.insert(AUTHORIZATION, authorization);
+            let http_client = reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?;
+            StreamableHttpClientTransport::with_client(
+                http_client,
+                StreamableHttpClientTransportConfig::with_uri(uri),
+            )
+        }
+        None => StreamableHttpClientTransport::from_uri(uri),
+    };
 
-    let client_handler = LocalClientHandler { progress_tx };
+    let client_handler = LocalClientHandler::new(progress_tx);
     let client: RunningService<RoleClient, LocalClientHandler> =
         client_handler.serve(transport).await?;
 
@@ -0,0 +1,119 @@
+use anyhow::{Result, anyhow};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rmcp::{
+    RoleClient, ServiceExt,
+    service::RunningService,
+    transport::{
+        StreamableHttpClientTransport, TokioChildProcess,
+        streamable_http_client::StreamableHttpClientTransportConfig,
+    },
+};
+use serde::{Deserialize, Serialize};
+use stakpak_shared::models::integrations::openai::ToolCallResultProgress;
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+
+use crate::LOCAL_CLIENT;
+use crate::local::LocalClientHandler;
+
+/// An MCP server the agent connects to besides its own, from the `[mcp_servers]` config
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum McpServerConfig {
+    /// A server started as a child process, speaking MCP over its stdin and stdout
+    Stdio {
+        command: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        /// Variables set for the process on top of the agent's environment
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        env: HashMap<String, String>,
+    },
+    /// A server reached over streamable HTTP, at the full URL of its MCP endpoint
+    Http {
+        url: String,
+        /// Headers sent with every request, e.g. `Authorization`
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
+}
+
+/// Server names prefix their tool names, so they are limited to the characters tool names can
+/// hold and can't contain the separator
+fn validate_server_name(name: &str) -> Result<()> {
+    if name == LOCAL_CLIENT {
+        return Err(anyhow!("The MCP server name '{}' is reserved", name));
+    }
+    if name.is_empty()
+        || name.contains(crate::TOOL_NAME_SEPARATOR)
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow!(
+            "Invalid MCP server name '{}', use letters, digits, '-' and single '_'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Transport to an HTTP server sending `headers` with every request, their values are marked
+/// sensitive so they are left out of logs
+fn http_transport(
+    url: &str,
+    headers: &HashMap<String, String>,
+) -> Result<StreamableHttpClientTransport<reqwest::Client>> {
+    if headers.is_empty() {
+        return Ok(StreamableHttpClientTransport::from_uri(url.to_string()));
+    }
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|e| anyhow!("Invalid value of header '{}': {}", name, e))?;
+        value.set_sensitive(true);
+        header_map.insert(name, value);
+    }
+    let http_client = reqwest::Client::builder()
+        .default_headers(header_map)
+        .build()?;
+    Ok(StreamableHttpClientTransport::with_client(
+        http_client,
+        StreamableHttpClientTransportConfig::with_uri(url.to_string()),
+    ))
+}
+
+/// Connect to a configured server, starting it first if it runs as a child process
+pub async fn server_client(
+    name: &str,
+    config: &McpServerConfig,
+    progress_tx: Option<Sender<ToolCallResultProgress>>,
+) -> Result<RunningService<RoleClient, LocalClientHandler>> {
+    validate_server_name(name)?;
+    let client_handler = LocalClientHandler::new(progress_tx);
+    match config {
+        McpServerConfig::Stdio { command, args, env } => {
+            let mut cmd = tokio::process::Command::new(command);
+            cmd.args(args)
+                .envs(env)
+                // The server's logs would draw over the TUI
+                .stderr(std::process::Stdio::null());
+            let transport = TokioChildProcess::new(&mut cmd)
+                .map_err(|e| anyhow!("Failed to start MCP server '{}': {}", name, e))?;
+            client_handler
+                .serve(transport)
+                .await
+                .map_err(|e| anyhow!("Failed to connect to MCP server '{}': {}", name, e))
+        }
+        McpServerConfig::Http { url, headers } => {
+            let transport = http_transport(url, headers)
+                .map_err(|e| anyhow!("Invalid config of MCP server '{}': {}", name, e))?;
+            client_handler
+                .serve(transport)
+                .await
+                .map_err(|e| anyhow!("Failed to connect to MCP server '{}': {}", name, e))
+        }
+    }
+}
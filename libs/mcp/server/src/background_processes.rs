@@ -0,0 +1,438 @@
+use rand::Rng;
+use stakpak_shared::local_store::LocalStore;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+use crate::secret_manager::SecretManager;
+
+/// Maximum number of output lines kept per background process
+const MAX_BUFFERED_LINES: usize = 1000;
+/// How long a process gets to exit after SIGTERM before it is killed
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessStatus {
+    Running,
+    /// Exit code, or None if the process was terminated by a signal
+    Exited(Option<i32>),
+}
+
+impl std::fmt::Display for ProcessStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessStatus::Running => write!(f, "running"),
+            ProcessStatus::Exited(Some(code)) => write!(f, "exited with code {}", code),
+            ProcessStatus::Exited(None) => write!(f, "terminated by signal"),
+        }
+    }
+}
+
+/// Ring buffer holding the most recent output lines of a process
+#[derive(Default)]
+struct OutputBuffer {
+    lines: VecDeque<String>,
+    /// Total number of lines ever written, readers use it as a cursor
+    total_lines: usize,
+}
+
+impl OutputBuffer {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == MAX_BUFFERED_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        self.total_lines += 1;
+    }
+
+    /// Cursor of the oldest line still held in the buffer
+    fn first_offset(&self) -> usize {
+        self.total_lines - self.lines.len()
+    }
+}
+
+pub struct BackgroundProcess {
+    pub id: String,
+    /// The command as requested, secrets stay as redaction placeholders
+    pub command: String,
+    pub pid: Option<u32>,
+    started_at: Instant,
+    output: Arc<Mutex<OutputBuffer>>,
+    stdin: tokio::sync::Mutex<Option<ChildStdin>>,
+    status_rx: watch::Receiver<ProcessStatus>,
+    kill: CancellationToken,
+}
+
+impl BackgroundProcess {
+    pub fn status(&self) -> ProcessStatus {
+        *self.status_rx.borrow()
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn total_lines(&self) -> usize {
+        lock(&self.output).total_lines
+    }
+
+    fn log_file_name(&self) -> String {
+        format!("background.{}.log", self.id)
+    }
+}
+
+/// A chunk of output read from a background process
+pub struct ProcessOutput {
+    pub status: ProcessStatus,
+    /// Redacted output lines
+    pub text: String,
+    /// Cursor of the first returned line
+    pub start_offset: usize,
+    /// Cursor to pass on the next read to only get new output
+    pub next_offset: usize,
+    /// Number of requested lines that were already dropped from the ring buffer
+    pub dropped_lines: usize,
+    /// Session file holding the buffered output
+    pub log_path: Option<String>,
+}
+
+/// Keeps track of long-running processes started by the agent
+#[derive(Clone)]
+pub struct ProcessRegistry {
+    processes: Arc<Mutex<HashMap<String, Arc<BackgroundProcess>>>>,
+    secret_manager: SecretManager,
+}
+
+impl ProcessRegistry {
+    pub fn new(secret_manager: SecretManager) -> Self {
+        Self {
+            processes: Arc::new(Mutex::new(HashMap::new())),
+            secret_manager,
+        }
+    }
+
+    /// Spawn a command in the background, `actual_command` is the command with secrets restored
+    pub fn start(
+        &self,
+        command: &str,
+        actual_command: &str,
+        work_dir: Option<String>,
+    ) -> Result<Arc<BackgroundProcess>, String> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(actual_command)
+            .current_dir(work_dir.unwrap_or(".".to_string()))
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("Failed to start command: {}", e))?;
+
+        // Hold the registry lock until the process is inserted so no other start takes the id
+        let mut processes = lock(&self.processes);
+        let id = loop {
+            let id = format!("proc_{:06x}", rand::rng().random_range(0..=0xFFFFFF));
+            if !processes.contains_key(&id) {
+                break id;
+            }
+        };
+        let output = Arc::new(Mutex::new(OutputBuffer::default()));
+        let (status_tx, status_rx) = watch::channel(ProcessStatus::Running);
+
+        if let Some(stdout) = child.stdout.take() {
+            spawn_output_reader(stdout, output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            spawn_output_reader(stderr, output.clone());
+        }
+
+        let process = Arc::new(BackgroundProcess {
+            id: id.clone(),
+            command: command.to_string(),
+            pid: child.id(),
+            started_at: Instant::now(),
+            output,
+            stdin: tokio::sync::Mutex::new(child.stdin.take()),
+            status_rx,
+            kill: CancellationToken::new(),
+        });
+
+        let registry = self.clone();
+        let waited_process = process.clone();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = waited_process.kill.cancelled() => {
+                    kill_process_group(&mut child).await;
+                    child.wait().await
+                }
+            };
+            let status = ProcessStatus::Exited(status.ok().and_then(|s| s.code()));
+            let _ = status_tx.send(status);
+            registry.persist_output(&waited_process);
+        });
+
+        processes.insert(id, process.clone());
+        Ok(process)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<BackgroundProcess>> {
+        lock(&self.processes).get(id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Arc<BackgroundProcess>> {
+        let mut processes: Vec<_> = lock(&self.processes).values().cloned().collect();
+        processes.sort_by_key(|p| p.started_at);
+        processes
+    }
+
+    /// Read buffered output starting at `offset`, or the last `limit` lines if no offset is given
+    pub fn read_output(
+        &self,
+        id: &str,
+        offset: Option<usize>,
+        limit: usize,
+    ) -> Result<ProcessOutput, String> {
+        let process = self
+            .get(id)
+            .ok_or_else(|| format!("No background process with id {}", id))?;
+
+        let (lines, start_offset, next_offset, dropped_lines) = {
+            let output = lock(&process.output);
+            let first_offset = output.first_offset();
+            let start = match offset {
+                Some(offset) => offset.clamp(first_offset, output.total_lines),
+                None => output.total_lines.saturating_sub(limit).max(first_offset),
+            };
+            let dropped_lines = offset.map_or(0, |o| first_offset.saturating_sub(o));
+            let lines = output
+                .lines
+                .iter()
+                .skip(start - first_offset)
+                .take(limit)
+                .cloned()
+                .collect::<Vec<_>>();
+            let next_offset = start + lines.len();
+            (lines, start, next_offset, dropped_lines)
+        };
+
+        let text = self
+            .secret_manager
+            .redact_and_store_secrets(&lines.join("\n"), None);
+        let log_path = self.persist_output(&process);
+
+        Ok(ProcessOutput {
+            status: process.status(),
+            text,
+            start_offset,
+            next_offset,
+            dropped_lines,
+            log_path,
+        })
+    }
+
+    /// Write `input` to the process stdin, secrets should already be restored
+    pub async fn send_input(&self, id: &str, input: &str) -> Result<(), String> {
+        let process = self
+            .get(id)
+            .ok_or_else(|| format!("No background process with id {}", id))?;
+
+        if process.status() != ProcessStatus::Running {
+            return Err(format!("Process {} is {}", id, process.status()));
+        }
+
+        let mut stdin = process.stdin.lock().await;
+        let stdin = stdin
+            .as_mut()
+            .ok_or_else(|| format!("Process {} does not accept input", id))?;
+        stdin
+            .write_all(input.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to write to process {}: {}", id, e))?;
+        stdin
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to write to process {}: {}", id, e))
+    }
+
+    /// Terminate the process (SIGTERM, then SIGKILL after a grace period) and forget about it
+    pub async fn stop(&self, id: &str) -> Result<Arc<BackgroundProcess>, String> {
+        let process = self
+            .get(id)
+            .ok_or_else(|| format!("No background process with id {}", id))?;
+
+        if process.status() == ProcessStatus::Running {
+            #[cfg(unix)]
+            if let Some(pid) = process.pid {
+                // SAFETY: killpg only sends a signal, the process was spawned as the leader of
+                // its own group so the pid is a group id that holds only this command's processes
+                unsafe {
+                    libc::killpg(pid as libc::pid_t, libc::SIGTERM);
+                }
+            }
+
+            let mut status_rx = process.status_rx.clone();
+            let exited = tokio::time::timeout(
+                STOP_GRACE_PERIOD,
+                status_rx.wait_for(|s| *s != ProcessStatus::Running),
+            )
+            .await
+            .is_ok();
+            if !exited {
+                process.kill.cancel();
+                let _ = status_rx.wait_for(|s| *s != ProcessStatus::Running).await;
+            }
+        }
+
+        lock(&self.processes).remove(id);
+        Ok(process)
+    }
+
+    /// Save the buffered output (redacted) to the session store
+    fn persist_output(&self, process: &BackgroundProcess) -> Option<String> {
+        let content = lock(&process.output)
+            .lines
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n");
+        let redacted_content = self.secret_manager.redact_and_store_secrets(&content, None);
+
+        match LocalStore::write_session_data(&process.log_file_name(), &redacted_content) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                error!("Failed to persist output of process {}: {}", process.id, e);
+                None
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn spawn_output_reader<R>(reader: R, output: Arc<Mutex<OutputBuffer>>)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            lock(&output).push(line);
+        }
+    });
+}
+
+/// Kill a command along with every process it spawned
+pub(crate) async fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg only sends a signal, the child was spawned as the leader of its own
+        // process group so its pid is the group id
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    if let Err(e) = child.kill().await {
+        error!("Failed to kill command: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> ProcessRegistry {
+        ProcessRegistry::new(SecretManager::new(false).expect("failed to create secret manager"))
+    }
+
+    /// Wait until the process exited and its output readers caught up
+    async fn wait_for_output(process: &BackgroundProcess, lines: usize) {
+        let mut status_rx = process.status_rx.clone();
+        let _ = status_rx.wait_for(|s| *s != ProcessStatus::Running).await;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process.total_lines() < lines && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn test_output_buffer_drops_oldest_lines() {
+        let mut buffer = OutputBuffer::default();
+        for i in 0..MAX_BUFFERED_LINES + 5 {
+            buffer.push(i.to_string());
+        }
+
+        assert_eq!(buffer.lines.len(), MAX_BUFFERED_LINES);
+        assert_eq!(buffer.total_lines, MAX_BUFFERED_LINES + 5);
+        assert_eq!(buffer.first_offset(), 5);
+        assert_eq!(buffer.lines.front().map(String::as_str), Some("5"));
+    }
+
+    #[tokio::test]
+    async fn test_read_output_offsets() {
+        let registry = registry();
+        let process = registry
+            .start("printf", "printf 'a\\nb\\nc\\n'", None)
+            .expect("failed to start process");
+        wait_for_output(&process, 3).await;
+
+        let tail = registry.read_output(&process.id, None, 2).unwrap();
+        assert_eq!(tail.text, "b\nc");
+        assert_eq!((tail.start_offset, tail.next_offset), (1, 3));
+
+        let all = registry.read_output(&process.id, Some(0), 10).unwrap();
+        assert_eq!(all.text, "a\nb\nc");
+        assert_eq!(all.dropped_lines, 0);
+
+        let new = registry
+            .read_output(&process.id, Some(all.next_offset), 10)
+            .unwrap();
+        assert_eq!(new.text, "");
+        assert_eq!(new.next_offset, 3);
+        assert_eq!(new.status, ProcessStatus::Exited(Some(0)));
+    }
+
+    #[tokio::test]
+    async fn test_read_output_reports_dropped_lines() {
+        let registry = registry();
+        let lines = MAX_BUFFERED_LINES + 5;
+        let process = registry
+            .start("seq", &format!("seq 1 {}", lines), None)
+            .expect("failed to start process");
+        wait_for_output(&process, lines).await;
+
+        let output = registry.read_output(&process.id, Some(0), 2).unwrap();
+        assert_eq!(output.dropped_lines, 5);
+        assert_eq!(output.start_offset, 5);
+        assert_eq!(output.text, "6\n7");
+        assert_eq!(output.next_offset, 7);
+    }
+
+    #[tokio::test]
+    async fn test_stop_terminates_and_forgets_process() {
+        let registry = registry();
+        let process = registry
+            .start("sleep", "sleep 30", None)
+            .expect("failed to start process");
+        assert_eq!(process.status(), ProcessStatus::Running);
+
+        registry.stop(&process.id).await.unwrap();
+
+        assert_ne!(process.status(), ProcessStatus::Running);
+        assert!(registry.get(&process.id).is_none());
+        assert!(registry.stop(&process.id).await.is_err());
+    }
+}
@@ -1,33 +1,124 @@
 use rmcp::{
-    Error as McpError, RoleServer, ServerHandler, model::*, schemars, service::RequestContext, tool,
+    Error as McpError, RoleServer, ServerHandler, handler::server::tool::ToolCallContext, model::*,
+    schemars, service::RequestContext, tool,
 };
 use stakpak_api::ClientConfig;
+use stakpak_shared::guardrails::Guardrails;
+use stakpak_shared::workspace::WorkspaceRoots;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
-use crate::local_tools::LocalTools;
+use crate::local_tools::{CommandShell, LocalTools, PatchEdit};
 use crate::remote_tools::{Provisioner, RemoteTools};
+use crate::resources::SESSION_URI_PREFIX;
+use crate::secret_manager::SecretManager;
 use crate::tool_descriptions::*;
+use crate::tool_profiles::{ToolFilter, tool_disabled};
 
 /// Combined tools that include both local and remote functionality
 #[derive(Clone)]
 pub struct CombinedTools {
     local_tools: LocalTools,
     remote_tools: RemoteTools,
+    tool_filter: Arc<ToolFilter>,
 }
 
 #[tool(tool_box)]
 impl CombinedTools {
-    pub fn new(api_config: ClientConfig, redact_secrets: bool) -> Self {
+    pub fn new(
+        api_config: ClientConfig,
+        secret_manager: SecretManager,
+        command_timeout: Option<Duration>,
+        guardrails: Arc<Guardrails>,
+        workspace: Arc<WorkspaceRoots>,
+        user_confirms_tool_calls: bool,
+        tool_filter: Arc<ToolFilter>,
+    ) -> Self {
+        // Every tool is called through this server, so only its filter applies
         Self {
-            local_tools: LocalTools::new(redact_secrets),
-            remote_tools: RemoteTools::new(api_config, redact_secrets),
+            local_tools: LocalTools::new(
+                secret_manager.clone(),
+                command_timeout,
+                guardrails,
+                workspace,
+                user_confirms_tool_calls,
+                Default::default(),
+            ),
+            remote_tools: RemoteTools::new(api_config, secret_manager, Default::default()),
+            tool_filter,
         }
     }
 
     // Local tools delegation
     #[tool(description = RUN_COMMAND_DESCRIPTION)]
+    #[allow(clippy::too_many_arguments)]
     pub async fn run_command(
         &self,
         peer: rmcp::Peer<RoleServer>,
+        ct: CancellationToken,
+        #[tool(param)]
+        #[schemars(description = COMMAND_PARAM_DESCRIPTION)]
+        command: String,
+        #[tool(param)]
+        #[schemars(description = WORK_DIR_PARAM_DESCRIPTION)]
+        work_dir: Option<String>,
+        #[tool(param)]
+        #[schemars(description = TIMEOUT_SECONDS_PARAM_DESCRIPTION)]
+        timeout_seconds: Option<u64>,
+        #[tool(param)]
+        #[schemars(description = ENV_PARAM_DESCRIPTION)]
+        env: Option<HashMap<String, String>>,
+        #[tool(param)]
+        #[schemars(description = STDIN_PARAM_DESCRIPTION)]
+        stdin: Option<String>,
+        #[tool(param)]
+        #[schemars(description = SHELL_PARAM_DESCRIPTION)]
+        shell: Option<CommandShell>,
+        #[tool(param)]
+        #[schemars(description = PTY_PARAM_DESCRIPTION)]
+        pty: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = TERMINAL_SIZE_PARAM_DESCRIPTION)]
+        terminal_size: Option<[u16; 2]>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools
+            .run_command(
+                peer,
+                ct,
+                command,
+                work_dir,
+                timeout_seconds,
+                env,
+                stdin,
+                shell,
+                pty,
+                terminal_size,
+            )
+            .await
+    }
+
+    #[tool(description = READ_COMMAND_OUTPUT_DESCRIPTION)]
+    pub fn read_command_output(
+        &self,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_ID_PARAM_DESCRIPTION)]
+        output_id: String,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_RANGE_PARAM_DESCRIPTION)]
+        view_range: Option<[i32; 2]>,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_GREP_PARAM_DESCRIPTION)]
+        grep: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools
+            .read_command_output(output_id, view_range, grep)
+    }
+
+    #[tool(description = START_BACKGROUND_DESCRIPTION)]
+    pub fn start_background(
+        &self,
         #[tool(param)]
         #[schemars(description = COMMAND_PARAM_DESCRIPTION)]
         command: String,
@@ -35,7 +126,57 @@ impl CombinedTools {
         #[schemars(description = WORK_DIR_PARAM_DESCRIPTION)]
         work_dir: Option<String>,
     ) -> Result<CallToolResult, McpError> {
-        self.local_tools.run_command(peer, command, work_dir).await
+        self.local_tools.start_background(command, work_dir)
+    }
+
+    #[tool(description = LIST_PROCESSES_DESCRIPTION)]
+    pub fn list_processes(&self) -> Result<CallToolResult, McpError> {
+        self.local_tools.list_processes()
+    }
+
+    #[tool(description = READ_PROCESS_OUTPUT_DESCRIPTION)]
+    pub fn read_process_output(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_OFFSET_PARAM_DESCRIPTION)]
+        offset: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_LIMIT_PARAM_DESCRIPTION)]
+        limit: Option<usize>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools
+            .read_process_output(process_id, offset, limit)
+    }
+
+    #[tool(description = SEND_PROCESS_INPUT_DESCRIPTION)]
+    pub async fn send_process_input(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+        #[tool(param)]
+        #[schemars(description = PROCESS_INPUT_PARAM_DESCRIPTION)]
+        input: String,
+        #[tool(param)]
+        #[schemars(description = NO_NEWLINE_PARAM_DESCRIPTION)]
+        no_newline: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools
+            .send_process_input(process_id, input, no_newline)
+            .await
+    }
+
+    #[tool(description = STOP_PROCESS_DESCRIPTION)]
+    pub async fn stop_process(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools.stop_process(process_id).await
     }
 
     #[tool(description = VIEW_DESCRIPTION)]
@@ -47,8 +188,87 @@ impl CombinedTools {
         #[tool(param)]
         #[schemars(description = VIEW_RANGE_PARAM_DESCRIPTION)]
         view_range: Option<[i32; 2]>,
+        #[tool(param)]
+        #[schemars(description = HEX_RANGE_PARAM_DESCRIPTION)]
+        hex_range: Option<[u64; 2]>,
+        #[tool(param)]
+        #[schemars(description = DEPTH_PARAM_DESCRIPTION)]
+        depth: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_HIDDEN_PARAM_DESCRIPTION)]
+        include_hidden: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = RESPECT_GITIGNORE_PARAM_DESCRIPTION)]
+        respect_gitignore: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools.view(
+            path,
+            view_range,
+            hex_range,
+            depth,
+            include_hidden,
+            respect_gitignore,
+        )
+    }
+
+    #[tool(description = GLOB_DESCRIPTION)]
+    pub fn glob(
+        &self,
+        #[tool(param)]
+        #[schemars(description = GLOB_PATTERN_PARAM_DESCRIPTION)]
+        pattern: String,
+        #[tool(param)]
+        #[schemars(description = SEARCH_PATH_PARAM_DESCRIPTION)]
+        path: Option<String>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_IGNORED_PARAM_DESCRIPTION)]
+        include_ignored: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = GLOB_LIMIT_PARAM_DESCRIPTION)]
+        limit: Option<usize>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools.glob(pattern, path, include_ignored, limit)
+    }
+
+    #[tool(description = GREP_DESCRIPTION)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn grep(
+        &self,
+        #[tool(param)]
+        #[schemars(description = GREP_PATTERN_PARAM_DESCRIPTION)]
+        pattern: String,
+        #[tool(param)]
+        #[schemars(description = SEARCH_PATH_PARAM_DESCRIPTION)]
+        path: Option<String>,
+        #[tool(param)]
+        #[schemars(description = CASE_INSENSITIVE_PARAM_DESCRIPTION)]
+        case_insensitive: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = CONTEXT_LINES_PARAM_DESCRIPTION)]
+        context_lines: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = GREP_INCLUDE_PARAM_DESCRIPTION)]
+        include: Option<String>,
+        #[tool(param)]
+        #[schemars(description = FILE_TYPE_PARAM_DESCRIPTION)]
+        file_type: Option<String>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_IGNORED_PARAM_DESCRIPTION)]
+        include_ignored: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = MAX_RESULTS_PARAM_DESCRIPTION)]
+        max_results: Option<usize>,
     ) -> Result<CallToolResult, McpError> {
-        self.local_tools.view(path, view_range)
+        self.local_tools.grep(
+            pattern,
+            path,
+            case_insensitive,
+            context_lines,
+            include,
+            file_type,
+            include_ignored,
+            max_results,
+        )
     }
 
     #[tool(description = STR_REPLACE_DESCRIPTION)]
@@ -84,6 +304,29 @@ impl CombinedTools {
         self.local_tools.create(path, file_text)
     }
 
+    #[tool(description = APPLY_PATCH_DESCRIPTION)]
+    pub fn apply_patch(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PATCH_PARAM_DESCRIPTION)]
+        patch: Option<String>,
+        #[tool(param)]
+        #[schemars(description = PATCH_EDITS_PARAM_DESCRIPTION)]
+        edits: Option<Vec<PatchEdit>>,
+    ) -> Result<CallToolResult, McpError> {
+        self.local_tools.apply_patch(patch, edits)
+    }
+
+    #[tool(description = UNDO_LAST_EDIT_DESCRIPTION)]
+    pub fn undo_last_edit(&self) -> Result<CallToolResult, McpError> {
+        self.local_tools.undo_last_edit()
+    }
+
+    #[tool(description = LIST_EDITS_DESCRIPTION)]
+    pub fn list_edits(&self) -> Result<CallToolResult, McpError> {
+        self.local_tools.list_edits()
+    }
+
     #[tool(description = GENERATE_PASSWORD_DESCRIPTION)]
     pub async fn generate_password(
         &self,
@@ -189,19 +432,69 @@ impl CombinedTools {
     }
 }
 
-#[tool(tool_box)]
 impl ServerHandler for CombinedTools {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_prompts()
+                .enable_resources()
+                .enable_tools()
+                .build(),
             server_info: Implementation::from_build_env(),
             instructions: Some(
-                "This server provides both local tools (file operations, command execution) and remote tools (code generation, smart search) that can run commands on the system and interact with Stakpak API.".to_string(),
+                "This server provides both local tools (file operations, command execution) and remote tools (code generation, smart search) that can run commands on the system and interact with Stakpak API. The session files, code index and rulebooks are available as resources, with a prompt to follow each rulebook.".to_string(),
             ),
         }
     }
 
+    async fn list_resources(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let mut resources = self
+            .local_tools
+            .list_resources(request.clone(), context.clone())
+            .await?
+            .resources;
+        resources.extend(
+            self.remote_tools
+                .list_resources(request, context)
+                .await?
+                .resources,
+        );
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if request.uri.starts_with(SESSION_URI_PREFIX) {
+            self.local_tools.read_resource(request, context).await
+        } else {
+            self.remote_tools.read_resource(request, context).await
+        }
+    }
+
+    async fn list_prompts(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        self.remote_tools.list_prompts(request, context).await
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        self.remote_tools.get_prompt(request, context).await
+    }
+
     async fn initialize(
         &self,
         _request: InitializeRequestParam,
@@ -209,4 +502,31 @@ impl ServerHandler for CombinedTools {
     ) -> Result<InitializeResult, McpError> {
         Ok(self.get_info())
     }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(
+            Self::tool_box()
+                .list()
+                .into_iter()
+                .filter(|tool| self.tool_filter.allows(&tool.name))
+                .collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.tool_filter.allows(&request.name) {
+            return Ok(tool_disabled(&request.name));
+        }
+        Self::tool_box()
+            .call(ToolCallContext::new(self, request, context))
+            .await
+    }
 }
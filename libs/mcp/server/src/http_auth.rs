@@ -0,0 +1,138 @@
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use std::sync::Arc;
+
+/// Bytes of randomness in generated tokens
+const TOKEN_BYTES: usize = 32;
+
+/// A random bearer token for one run of the server, clients must send it to use the tools
+pub fn generate_auth_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::rng().fill(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Who may call the HTTP endpoint
+#[derive(Clone, Debug, Default)]
+pub struct HttpAuth {
+    /// Bearer token every request must carry, None lets any client in
+    pub token: Option<String>,
+    /// Origins browsers may call from. Requests without an `Origin` header don't come from a
+    /// web page and aren't checked.
+    pub allowed_origins: Vec<String>,
+}
+
+impl HttpAuth {
+    /// An empty token would let in any client sending `Authorization: Bearer `
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return Err("The bearer token of the HTTP transport can't be empty".to_string());
+        }
+        Ok(())
+    }
+
+    fn origin_allowed(&self, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/');
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/') == origin)
+    }
+
+    fn token_valid(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|sent| constant_time_eq(sent.trim().as_bytes(), token.as_bytes()))
+    }
+}
+
+/// Compares without returning early, so response times don't reveal how much of a guessed
+/// token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Rejects requests from origins outside the allowlist, which would let any web page the user
+/// opens drive the tools, and requests without the bearer token
+pub async fn authorize(
+    State(auth): State<Arc<HttpAuth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.headers().get(header::ORIGIN).is_some_and(|origin| {
+        !origin
+            .to_str()
+            .is_ok_and(|origin| auth.origin_allowed(origin))
+    }) {
+        return (StatusCode::FORBIDDEN, "Origin not allowed").into_response();
+    }
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !auth.token_valid(authorization) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid bearer token",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> HttpAuth {
+        HttpAuth {
+            token: Some("secret-token".to_string()),
+            allowed_origins: vec!["http://localhost:3000/".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_token_valid() {
+        let auth = auth();
+        assert!(auth.token_valid(Some("Bearer secret-token")));
+        assert!(!auth.token_valid(None));
+        assert!(!auth.token_valid(Some("Bearer wrong-token")));
+        assert!(!auth.token_valid(Some("Bearer ")));
+        assert!(!auth.token_valid(Some("secret-token")));
+        assert!(HttpAuth::default().token_valid(None));
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let auth = auth();
+        assert!(auth.origin_allowed("http://localhost:3000"));
+        assert!(!auth.origin_allowed("https://evil.example"));
+        assert!(!HttpAuth::default().origin_allowed("http://localhost:3000"));
+        let any = HttpAuth {
+            allowed_origins: vec!["*".to_string()],
+            ..HttpAuth::default()
+        };
+        assert!(any.origin_allowed("https://evil.example"));
+    }
+
+    #[test]
+    fn test_empty_token_is_rejected() {
+        assert!(auth().validate().is_ok());
+        assert!(HttpAuth::default().validate().is_ok());
+        let empty = HttpAuth {
+            token: Some(" ".to_string()),
+            ..HttpAuth::default()
+        };
+        assert!(empty.validate().is_err());
+    }
+}
@@ -2,18 +2,32 @@ use anyhow::Result;
 use rmcp::transport::streamable_http_server::{
     StreamableHttpService, session::local::LocalSessionManager,
 };
+use rmcp::{ServerHandler, ServiceExt};
 
 use stakpak_api::ClientConfig;
+use stakpak_shared::guardrails::Guardrails;
+use stakpak_shared::workspace::WorkspaceRoots;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
+pub mod background_processes;
 pub mod combined_tools;
+pub mod http_auth;
 pub mod local_tools;
+pub mod pty_command;
 pub mod remote_tools;
+pub mod resources;
 pub mod secret_manager;
 pub mod tool_descriptions;
+pub mod tool_profiles;
 
 pub use combined_tools::CombinedTools;
+pub use http_auth::{HttpAuth, generate_auth_token};
 pub use local_tools::LocalTools;
 pub use remote_tools::RemoteTools;
+pub use secret_manager::SecretManager;
+pub use tool_profiles::{ToolFilter, ToolProfile};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ToolMode {
@@ -49,11 +63,55 @@ impl std::str::FromStr for ToolMode {
     }
 }
 
+/// How MCP clients reach the server
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum McpTransport {
+    /// JSON-RPC over stdin and stdout, for hosts that start the server as a child process
+    Stdio,
+    /// Streamable HTTP at `/mcp` on the bind address
+    Http,
+}
+
+impl std::fmt::Display for McpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            McpTransport::Stdio => "stdio",
+            McpTransport::Http => "http",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::str::FromStr for McpTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "stdio" => Ok(McpTransport::Stdio),
+            "http" => Ok(McpTransport::Http),
+            _ => Err(format!("Invalid transport: {}", s)),
+        }
+    }
+}
+
 pub struct MCPServerConfig {
     pub api: ClientConfig,
+    pub transport: McpTransport,
+    /// Address the HTTP transport listens on, unused over stdio
     pub bind_address: String,
+    /// Who may call the HTTP transport, unused over stdio
+    pub http_auth: HttpAuth,
     pub redact_secrets: bool,
     pub tool_mode: ToolMode,
+    /// Default upper bound for `run_command` when the tool call doesn't set its own timeout
+    pub command_timeout: Option<Duration>,
+    /// Whether a user confirms every tool call before it's sent to the server, commands the
+    /// guardrails escalate are blocked otherwise
+    pub user_confirms_tool_calls: bool,
+    /// Directories besides the working directory that the file tools may access
+    pub allowed_paths: Vec<String>,
+    /// Tools the server lists and lets clients call
+    pub tool_filter: ToolFilter,
 }
 
 /// Initialize gitleaks configuration if secret redaction is enabled
@@ -151,49 +209,135 @@ async fn create_shutdown_handler(shutdown_rx: Option<tokio::sync::broadcast::Rec
     }
 }
 
-/// npx @modelcontextprotocol/inspector cargo run mcp
-pub async fn start_server(
-    config: MCPServerConfig,
+/// Serve the tools over `transport`. Over HTTP every session gets its own service, over stdio
+/// the one client gets a single service until it closes its input.
+async fn serve<S: ServerHandler>(
+    transport: McpTransport,
+    make_service: impl Fn() -> S + Send + Sync + 'static,
+    bind_address: String,
+    http_auth: HttpAuth,
     shutdown_rx: Option<tokio::sync::broadcast::Receiver<()>>,
 ) -> Result<()> {
-    init_gitleaks_if_needed(config.redact_secrets).await;
-
-    match config.tool_mode {
-        ToolMode::LocalOnly => {
+    match transport {
+        McpTransport::Http => {
             let service = StreamableHttpService::new(
-                move || LocalTools::new(config.redact_secrets),
+                make_service,
                 LocalSessionManager::default().into(),
                 Default::default(),
             );
-            let router = axum::Router::new().nest_service("/mcp", service);
-            let tcp_listener = tokio::net::TcpListener::bind(config.bind_address).await?;
+            let router = axum::Router::new().nest_service("/mcp", service).layer(
+                axum::middleware::from_fn_with_state(
+                    Arc::new(http_auth),
+                    crate::http_auth::authorize,
+                ),
+            );
+            let tcp_listener = tokio::net::TcpListener::bind(bind_address).await?;
             axum::serve(tcp_listener, router)
                 .with_graceful_shutdown(create_shutdown_handler(shutdown_rx))
                 .await?;
         }
-        ToolMode::RemoteOnly => {
-            let service = StreamableHttpService::new(
-                move || RemoteTools::new(config.api.clone(), config.redact_secrets),
-                LocalSessionManager::default().into(),
-                Default::default(),
-            );
-            let router = axum::Router::new().nest_service("/mcp", service);
-            let tcp_listener = tokio::net::TcpListener::bind(config.bind_address).await?;
-            axum::serve(tcp_listener, router)
-                .with_graceful_shutdown(create_shutdown_handler(shutdown_rx))
+        McpTransport::Stdio => {
+            let ct = CancellationToken::new();
+            let shutdown_ct = ct.clone();
+            tokio::spawn(async move {
+                create_shutdown_handler(shutdown_rx).await;
+                shutdown_ct.cancel();
+            });
+            let service = make_service()
+                .serve_with_ct(rmcp::transport::stdio(), ct)
                 .await?;
+            service.waiting().await?;
+        }
+    }
+    Ok(())
+}
+
+/// npx @modelcontextprotocol/inspector cargo run mcp
+pub async fn start_server(
+    config: MCPServerConfig,
+    shutdown_rx: Option<tokio::sync::broadcast::Receiver<()>>,
+) -> Result<()> {
+    config.http_auth.validate().map_err(anyhow::Error::msg)?;
+    init_gitleaks_if_needed(config.redact_secrets).await;
+
+    // Shared by every session so concurrent tool calls agree on redaction keys
+    let secret_manager = SecretManager::new(config.redact_secrets).map_err(anyhow::Error::msg)?;
+
+    let workdir = std::env::current_dir()?;
+    let (guardrails, guardrails_error) = Guardrails::load(&workdir);
+    if let Some(e) = guardrails_error {
+        tracing::warn!("Using the built-in guardrails only: {}", e);
+    }
+    let guardrails = Arc::new(guardrails);
+
+    let (workspace, workspace_errors) = WorkspaceRoots::new(&workdir, &config.allowed_paths);
+    for e in workspace_errors {
+        tracing::warn!("{}", e);
+    }
+    let workspace = Arc::new(workspace);
+    let tool_filter = Arc::new(config.tool_filter);
+
+    let transport = config.transport;
+    let bind_address = config.bind_address;
+    let http_auth = config.http_auth;
+    match config.tool_mode {
+        ToolMode::LocalOnly => {
+            let make_service = move || {
+                LocalTools::new(
+                    secret_manager.clone(),
+                    config.command_timeout,
+                    guardrails.clone(),
+                    workspace.clone(),
+                    config.user_confirms_tool_calls,
+                    tool_filter.clone(),
+                )
+            };
+            serve(
+                transport,
+                make_service,
+                bind_address,
+                http_auth,
+                shutdown_rx,
+            )
+            .await?;
+        }
+        ToolMode::RemoteOnly => {
+            let make_service = move || {
+                RemoteTools::new(
+                    config.api.clone(),
+                    secret_manager.clone(),
+                    tool_filter.clone(),
+                )
+            };
+            serve(
+                transport,
+                make_service,
+                bind_address,
+                http_auth,
+                shutdown_rx,
+            )
+            .await?;
         }
         ToolMode::Combined => {
-            let service = StreamableHttpService::new(
-                move || CombinedTools::new(config.api.clone(), config.redact_secrets),
-                LocalSessionManager::default().into(),
-                Default::default(),
-            );
-            let router = axum::Router::new().nest_service("/mcp", service);
-            let tcp_listener = tokio::net::TcpListener::bind(config.bind_address).await?;
-            axum::serve(tcp_listener, router)
-                .with_graceful_shutdown(create_shutdown_handler(shutdown_rx))
-                .await?;
+            let make_service = move || {
+                CombinedTools::new(
+                    config.api.clone(),
+                    secret_manager.clone(),
+                    config.command_timeout,
+                    guardrails.clone(),
+                    workspace.clone(),
+                    config.user_confirms_tool_calls,
+                    tool_filter.clone(),
+                )
+            };
+            serve(
+                transport,
+                make_service,
+                bind_address,
+                http_auth,
+                shutdown_rx,
+            )
+            .await?;
         }
     }
 
@@ -203,6 +347,7 @@ pub async fn start_server(
 /// Start server with local tools only (no API key required)
 pub async fn start_local_server(
     bind_address: String,
+    auth_token: String,
     redact_secrets: bool,
     shutdown_rx: Option<tokio::sync::broadcast::Receiver<()>>,
 ) -> Result<()> {
@@ -212,9 +357,18 @@ pub async fn start_local_server(
                 api_key: None,
                 api_endpoint: "".to_string(),
             },
+            transport: McpTransport::Http,
             bind_address,
+            http_auth: HttpAuth {
+                token: Some(auth_token),
+                allowed_origins: Vec::new(),
+            },
             redact_secrets,
             tool_mode: ToolMode::LocalOnly,
+            command_timeout: None,
+            user_confirms_tool_calls: false,
+            allowed_paths: Vec::new(),
+            tool_filter: ToolFilter::default(),
         },
         shutdown_rx,
     )
@@ -225,15 +379,25 @@ pub async fn start_local_server(
 pub async fn start_remote_server(
     api_config: ClientConfig,
     bind_address: String,
+    auth_token: String,
     redact_secrets: bool,
     shutdown_rx: Option<tokio::sync::broadcast::Receiver<()>>,
 ) -> Result<()> {
     start_server(
         MCPServerConfig {
             api: api_config,
+            transport: McpTransport::Http,
             bind_address,
+            http_auth: HttpAuth {
+                token: Some(auth_token),
+                allowed_origins: Vec::new(),
+            },
             redact_secrets,
             tool_mode: ToolMode::RemoteOnly,
+            command_timeout: None,
+            user_confirms_tool_calls: false,
+            allowed_paths: Vec::new(),
+            tool_filter: ToolFilter::default(),
         },
         shutdown_rx,
     )
@@ -244,15 +408,25 @@ pub async fn start_remote_server(
 pub async fn start_combined_server(
     api_config: ClientConfig,
     bind_address: String,
+    auth_token: String,
     redact_secrets: bool,
     shutdown_rx: Option<tokio::sync::broadcast::Receiver<()>>,
 ) -> Result<()> {
     start_server(
         MCPServerConfig {
             api: api_config,
+            transport: McpTransport::Http,
             bind_address,
+            http_auth: HttpAuth {
+                token: Some(auth_token),
+                allowed_origins: Vec::new(),
+            },
             redact_secrets,
             tool_mode: ToolMode::Combined,
+            command_timeout: None,
+            user_confirms_tool_calls: false,
+            allowed_paths: Vec::new(),
+            tool_filter: ToolFilter::default(),
         },
         shutdown_rx,
     )
@@ -1,71 +1,590 @@
-use rand::Rng;
 use rmcp::{
-    Error as McpError, RoleServer, ServerHandler, model::*, schemars, service::RequestContext, tool,
+    Error as McpError, RoleServer, ServerHandler, handler::server::tool::ToolCallContext, model::*,
+    schemars, service::RequestContext, tool,
 };
 
+use portable_pty::CommandBuilder;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use stakpak_shared::local_store::LocalStore;
+use stakpak_shared::command_output::{load_output, save_output, select_lines};
+use stakpak_shared::edit_journal::EditJournal;
+use stakpak_shared::file_reader::{
+    FileKind, MAX_HEXDUMP_BYTES, MAX_LINE_CHARS, hexdump, read_lines,
+};
+use stakpak_shared::file_tree::{TreeOptions, read_tree, render_tree};
+use stakpak_shared::guardrails::{GuardrailAction, Guardrails};
+use stakpak_shared::patch::{FilePatch, TextEdit, apply_edits, parse_unified_diff};
+use stakpak_shared::search::{self, GrepOptions};
+use stakpak_shared::workspace::WorkspaceRoots;
+use std::collections::HashMap;
 use std::fs;
 
-use std::path::Path;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use tracing::error;
 use uuid::Uuid;
 
+use crate::background_processes::{ProcessRegistry, ProcessStatus, kill_process_group};
+use crate::pty_command::{self, ChunkDecoder, PtyProcess};
+use crate::resources;
 use crate::secret_manager::SecretManager;
 use crate::tool_descriptions::*;
+use crate::tool_profiles::{ToolFilter, tool_disabled};
 use stakpak_shared::models::integrations::openai::ToolCallResultProgress;
 
+/// Why a running command was stopped before it exited on its own
+enum CommandInterruption {
+    TimedOut(Duration),
+    Cancelled,
+}
+
+impl std::fmt::Display for CommandInterruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandInterruption::TimedOut(timeout) => write!(
+                f,
+                "[TIMED OUT] Command timed out after {} seconds and was killed, the output above is partial",
+                timeout.as_secs()
+            ),
+            CommandInterruption::Cancelled => write!(
+                f,
+                "[CANCELLED] Command was cancelled and killed, the output above is partial"
+            ),
+        }
+    }
+}
+
+/// Structured result of run_command. The output streams are only in the text result, so
+/// they aren't sent twice.
+#[derive(Debug, Default, Serialize)]
+struct CommandResult {
+    /// None when the command was killed by a signal or interrupted
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    duration_ms: u64,
+    timed_out: bool,
+    cancelled: bool,
+    stdout_truncated: bool,
+    stderr_truncated: bool,
+    /// Ids of the full outputs of truncated streams, for read_command_output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdout_output_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr_output_id: Option<String>,
+    /// [columns, rows] of the terminal of PTY commands, whose output is all in stdout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    terminal_size: Option<[u16; 2]>,
+}
+
+/// What a command printed and how it ended, before redaction
+#[derive(Default)]
+struct CommandOutcome {
+    stdout: String,
+    stderr: String,
+    interruption: Option<CommandInterruption>,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+}
+
+/// A command output stream cut to its last lines
+struct TruncatedOutput {
+    /// Tells where the full output was saved, empty when nothing was cut
+    header: String,
+    tail: String,
+    output_id: Option<String>,
+}
+
+/// Record an edit in the session's edit journal so it can be undone. Failing to record it
+/// doesn't fail the edit, which has already been made.
+fn journal_edit(tool: &str, path: &Path, before: Option<String>, after: String) {
+    let recorded = EditJournal::update_session(|journal| {
+        journal.record(tool, path, before, after);
+        Ok(())
+    });
+    if let Err(e) = recorded {
+        error!("Failed to record the edit of {}: {}", path.display(), e);
+    }
+}
+
+/// Shell run_command runs commands with
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandShell {
+    #[default]
+    Sh,
+    Bash,
+    Zsh,
+    Pwsh,
+}
+
+impl CommandShell {
+    /// The program and the arguments that come before the command
+    fn invocation(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            CommandShell::Sh => ("sh", &["-c"]),
+            CommandShell::Bash => ("bash", &["-c"]),
+            CommandShell::Zsh => ("zsh", &["-c"]),
+            CommandShell::Pwsh => ("pwsh", &["-NoProfile", "-NonInteractive", "-Command"]),
+        }
+    }
+}
+
+/// Whether setting an environment variable makes shells or the dynamic loader run code of
+/// its value, e.g. `BASH_ENV` naming a script bash runs before the command
+fn runs_code(name: &str) -> bool {
+    const CODE_VARIABLES: &[&str] = &[
+        "BASH_ENV",
+        "ENV",
+        "ZDOTDIR",
+        "PROMPT_COMMAND",
+        "SHELLOPTS",
+        "BASHOPTS",
+        "PS4",
+        "LD_PRELOAD",
+        "LD_AUDIT",
+        "DYLD_INSERT_LIBRARIES",
+    ];
+    CODE_VARIABLES.contains(&name) || name.starts_with("BASH_FUNC_")
+}
+
+/// A str_replace style edit made by apply_patch
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PatchEdit {
+    #[schemars(description = FILE_PATH_PARAM_DESCRIPTION)]
+    pub path: String,
+    #[schemars(description = PATCH_OLD_STR_PARAM_DESCRIPTION)]
+    pub old_str: String,
+    #[schemars(description = NEW_STR_PARAM_DESCRIPTION)]
+    pub new_str: String,
+    #[schemars(description = REPLACE_ALL_PARAM_DESCRIPTION)]
+    pub replace_all: Option<bool>,
+}
+
+/// A file apply_patch has validated and is about to write
+struct PlannedWrite {
+    path: String,
+    resolved_path: PathBuf,
+    before: Option<String>,
+    after: String,
+}
+
+/// Group the edits by file, keeping the order they are made in
+fn group_edits(edits: Vec<PatchEdit>) -> Vec<FilePatch> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    for edit in edits {
+        let text_edit = TextEdit {
+            old_str: edit.old_str,
+            new_str: edit.new_str,
+            replace_all: edit.replace_all.unwrap_or(false),
+            insert_line: None,
+        };
+        match patches.iter_mut().find(|patch| patch.path == edit.path) {
+            Some(patch) => patch.edits.push(text_edit),
+            None => patches.push(FilePatch {
+                path: edit.path,
+                create: false,
+                edits: vec![text_edit],
+            }),
+        }
+    }
+    patches
+}
+
+/// Restore the files written by an apply_patch call that failed part way
+fn roll_back(written: &[&PlannedWrite]) {
+    for write in written.iter().rev() {
+        let restored = match &write.before {
+            Some(before) => fs::write(&write.resolved_path, before),
+            None => fs::remove_file(&write.resolved_path),
+        };
+        if let Err(e) = restored {
+            error!("Failed to roll back {}: {}", write.path, e);
+        }
+    }
+}
+
 /// Local tools that work without API access
 #[derive(Clone)]
 pub struct LocalTools {
     secret_manager: SecretManager,
+    command_timeout: Option<Duration>,
+    process_registry: ProcessRegistry,
+    guardrails: Arc<Guardrails>,
+    workspace: Arc<WorkspaceRoots>,
+    /// Whether a user confirms every tool call before it reaches the server, in which case
+    /// commands the guardrails escalate are allowed to run
+    user_confirms_tool_calls: bool,
+    tool_filter: Arc<ToolFilter>,
 }
 
 #[tool(tool_box)]
 impl LocalTools {
-    pub fn new(redact_secrets: bool) -> Self {
+    pub fn new(
+        secret_manager: SecretManager,
+        command_timeout: Option<Duration>,
+        guardrails: Arc<Guardrails>,
+        workspace: Arc<WorkspaceRoots>,
+        user_confirms_tool_calls: bool,
+        tool_filter: Arc<ToolFilter>,
+    ) -> Self {
         Self {
-            secret_manager: SecretManager::new(redact_secrets),
+            process_registry: ProcessRegistry::new(secret_manager.clone()),
+            secret_manager,
+            command_timeout,
+            guardrails,
+            workspace,
+            user_confirms_tool_calls,
+            tool_filter,
         }
     }
 
+    /// The absolute path with symlinks resolved, or an error result for paths outside the
+    /// directories the file tools may access
+    fn resolve_path(&self, path: &str) -> Result<PathBuf, CallToolResult> {
+        self.workspace.resolve(path).map_err(|error| {
+            CallToolResult::error(vec![
+                Content::text("PATH_OUTSIDE_WORKSPACE"),
+                Content::text(error),
+            ])
+        })
+    }
+
+    /// The directory or file glob and grep search, which must exist in the workspace
+    fn search_root(&self, path: &str) -> Result<PathBuf, CallToolResult> {
+        let root = self.resolve_path(path)?;
+        if !root.exists() {
+            return Err(CallToolResult::error(vec![
+                Content::text("FILE_NOT_FOUND"),
+                Content::text(format!("File or directory not found: {}", path)),
+            ]));
+        }
+        Ok(root)
+    }
+
+    /// Glob and grep results as JSON, with the secrets in matched lines redacted
+    fn search_result(&self, result: &impl serde::Serialize) -> CallToolResult {
+        match serde_json::to_string_pretty(result) {
+            Ok(json) => CallToolResult::success(vec![Content::text(
+                self.secret_manager.redact_and_store_secrets(&json, None),
+            )]),
+            Err(e) => CallToolResult::error(vec![
+                Content::text("SERIALIZATION_ERROR"),
+                Content::text(format!("Cannot serialize the results: {}", e)),
+            ]),
+        }
+    }
+
+    /// The last lines of a redacted command output stream. Longer outputs are saved in full
+    /// to be read with read_command_output.
+    fn truncated_output(&self, output: &str, stream: &str) -> Result<TruncatedOutput, McpError> {
+        const MAX_LINES: usize = 300;
+
+        let redacted = self.secret_manager.redact_and_store_secrets(output, None);
+        let lines = redacted.lines().collect::<Vec<_>>();
+        if lines.len() <= MAX_LINES {
+            return Ok(TruncatedOutput {
+                header: String::new(),
+                tail: redacted,
+                output_id: None,
+            });
+        }
+
+        let (output_id, _) = save_output(&redacted).map_err(|e| {
+            error!("Failed to save command output: {}", e);
+            McpError::internal_error(
+                "Failed to write session data",
+                Some(json!({ "error": e.to_string() })),
+            )
+        })?;
+        Ok(TruncatedOutput {
+            header: format!(
+                "Showing the last {} / {} {} lines. Full {} saved with id {}, use read_command_output to read or search it\n...\n",
+                MAX_LINES,
+                lines.len(),
+                stream,
+                stream,
+                output_id
+            ),
+            tail: format!("{}\n", lines[lines.len() - MAX_LINES..].join("\n")),
+            output_id: Some(output_id),
+        })
+    }
+
+    /// The result of a finished command, with its output redacted and truncated
+    fn command_result(
+        &self,
+        outcome: CommandOutcome,
+        started: Instant,
+        terminal_size: Option<[u16; 2]>,
+    ) -> Result<CallToolResult, McpError> {
+        let CommandOutcome {
+            stdout,
+            stderr,
+            interruption,
+            exit_code,
+            signal,
+        } = outcome;
+        let mut result = CommandResult {
+            exit_code,
+            signal,
+            duration_ms: started.elapsed().as_millis() as u64,
+            timed_out: matches!(interruption, Some(CommandInterruption::TimedOut(_))),
+            cancelled: matches!(interruption, Some(CommandInterruption::Cancelled)),
+            terminal_size,
+            ..Default::default()
+        };
+
+        // Redact before saving the full output, so it never holds secrets
+        let stdout_tail = self.truncated_output(&stdout, "stdout")?;
+        let stderr_tail = self.truncated_output(&stderr, "stderr")?;
+        result.stdout_truncated = stdout_tail.output_id.is_some();
+        result.stdout_output_id = stdout_tail.output_id;
+        result.stderr_truncated = stderr_tail.output_id.is_some();
+        result.stderr_output_id = stderr_tail.output_id;
+
+        let mut text = String::new();
+        if !stdout_tail.tail.is_empty() {
+            text.push_str(&format!("{}{}", stdout_tail.header, stdout_tail.tail));
+        }
+        if !stderr_tail.tail.is_empty() {
+            text.push_str(&format!(
+                "[stderr]\n{}{}",
+                stderr_tail.header, stderr_tail.tail
+            ));
+        }
+        if text.is_empty() {
+            text.push_str("No output\n");
+        }
+        let duration = Duration::from_millis(result.duration_ms);
+        match (&interruption, result.exit_code, result.signal) {
+            (Some(interruption), _, _) => text.push_str(&format!("{}\n", interruption)),
+            (None, _, Some(signal)) => text.push_str(&format!(
+                "Command was killed by signal {} after {:.2}s\n",
+                signal,
+                duration.as_secs_f64()
+            )),
+            (None, Some(code), None) => text.push_str(&format!(
+                "Command exited with code {} after {:.2}s\n",
+                code,
+                duration.as_secs_f64()
+            )),
+            (None, None, None) => {}
+        }
+
+        let json = serde_json::to_string(&result).unwrap_or_default();
+        Ok(CallToolResult::success(vec![
+            Content::text(text.trim_end()),
+            // The structured result is only for the agent, the text is shown to the user
+            Content::text(json).with_audience(vec![Role::Assistant]),
+        ]))
+    }
+
+    /// Error result for commands the guardrails don't let run, checking every input a shell
+    /// may run: the command, its stdin and its environment values. The message names the rule
+    /// rather than echoing the command, which may contain restored secrets.
+    fn check_guardrails<'a>(
+        &self,
+        inputs: impl IntoIterator<Item = &'a str>,
+        work_dir: Option<&str>,
+    ) -> Option<CallToolResult> {
+        let mut violations = inputs
+            .into_iter()
+            .filter_map(|input| self.guardrails.check(input, work_dir));
+        let first = violations.next()?;
+        let violation = match first.action {
+            GuardrailAction::Block => first,
+            _ => violations
+                .find(|violation| violation.action == GuardrailAction::Block)
+                .unwrap_or(first),
+        };
+        let message = match violation.action {
+            GuardrailAction::Escalate if self.user_confirms_tool_calls => return None,
+            GuardrailAction::Escalate => format!(
+                "Command blocked by guardrail rule {}: {}. It needs to be confirmed by a user, ask the user to run it or to allow it in .stakpak/guardrails.toml",
+                violation.rule_id, violation.description
+            ),
+            _ => format!(
+                "Command blocked by guardrail rule {}: {}. Do not retry it, use another approach or ask the user",
+                violation.rule_id, violation.description
+            ),
+        };
+        tracing::warn!(
+            "Blocked command by guardrail rule {} ({})",
+            violation.rule_id,
+            violation.action
+        );
+        Some(CallToolResult::error(vec![
+            Content::text("BLOCKED_BY_POLICY"),
+            Content::text(message),
+        ]))
+    }
+
     #[tool(description = RUN_COMMAND_DESCRIPTION)]
+    #[allow(clippy::too_many_arguments)]
     pub async fn run_command(
         &self,
         peer: rmcp::Peer<RoleServer>,
+        ct: CancellationToken,
         #[tool(param)]
         #[schemars(description = COMMAND_PARAM_DESCRIPTION)]
         command: String,
         #[tool(param)]
         #[schemars(description = WORK_DIR_PARAM_DESCRIPTION)]
         work_dir: Option<String>,
+        #[tool(param)]
+        #[schemars(description = TIMEOUT_SECONDS_PARAM_DESCRIPTION)]
+        timeout_seconds: Option<u64>,
+        #[tool(param)]
+        #[schemars(description = ENV_PARAM_DESCRIPTION)]
+        env: Option<HashMap<String, String>>,
+        #[tool(param)]
+        #[schemars(description = STDIN_PARAM_DESCRIPTION)]
+        stdin: Option<String>,
+        #[tool(param)]
+        #[schemars(description = SHELL_PARAM_DESCRIPTION)]
+        shell: Option<CommandShell>,
+        #[tool(param)]
+        #[schemars(description = PTY_PARAM_DESCRIPTION)]
+        pty: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = TERMINAL_SIZE_PARAM_DESCRIPTION)]
+        terminal_size: Option<[u16; 2]>,
     ) -> Result<CallToolResult, McpError> {
-        const MAX_LINES: usize = 300;
-
         let command_clone = command.clone();
 
         // Restore secrets in the command before execution
         let actual_command = self.secret_manager.restore_secrets_in_string(&command);
 
-        let mut child = Command::new("sh")
-            .arg("-c")
+        let env = env.unwrap_or_default();
+        if let Some(name) = env
+            .keys()
+            .find(|name| name.is_empty() || name.contains(['=', '\0']))
+        {
+            return Ok(CallToolResult::error(vec![
+                Content::text("INVALID_ENV"),
+                Content::text(format!("Invalid environment variable name '{}'", name)),
+            ]));
+        }
+        if let Some(name) = env.keys().find(|name| runs_code(name)) {
+            return Ok(CallToolResult::error(vec![
+                Content::text("INVALID_ENV"),
+                Content::text(format!(
+                    "Environment variable '{}' makes the shell or the loader run code, it can't be set with env",
+                    name
+                )),
+            ]));
+        }
+
+        let (program, shell_args) = shell.unwrap_or_default().invocation();
+        let work_dir = work_dir.unwrap_or(".".to_string());
+        // Restore secrets in the variables and stdin just before execution, so they never
+        // appear in the command itself
+        let env = env
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.clone(),
+                    self.secret_manager.restore_secrets_in_string(value),
+                )
+            })
+            .collect::<Vec<_>>();
+        let stdin = stdin.map(|input| self.secret_manager.restore_secrets_in_string(&input));
+
+        // A shell runs its stdin as a script, and environment values can end up as code
+        let inputs = std::iter::once(actual_command.as_str())
+            .chain(stdin.as_deref())
+            .chain(env.iter().map(|(_, value)| value.as_str()));
+        if let Some(blocked) = self.check_guardrails(inputs, Some(&work_dir)) {
+            return Ok(blocked);
+        }
+
+        let timeout = timeout_seconds
+            .map(Duration::from_secs)
+            .or(self.command_timeout);
+
+        if pty.unwrap_or(false) {
+            let size = pty_command::terminal_size(terminal_size);
+            let mut builder = CommandBuilder::new(program);
+            builder.args(shell_args);
+            builder.arg(&actual_command);
+            builder.cwd(&work_dir);
+            // Programs only color their output for terminals they know
+            builder.env("TERM", "xterm-256color");
+            for (name, value) in &env {
+                builder.env(name, value);
+            }
+
+            let started = Instant::now();
+            let (process, output) = match PtyProcess::spawn(builder, size, stdin) {
+                Ok(spawned) => spawned,
+                Err(e) => {
+                    error!("Failed to run command in a PTY: {}", e);
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("PTY_ERROR"),
+                        Content::text(format!(
+                            "Cannot run the command in a pseudo terminal: {}",
+                            e
+                        )),
+                    ]));
+                }
+            };
+            let outcome = run_in_pty(process, output, &peer, &ct, timeout).await;
+            return self.command_result(outcome, started, Some(size));
+        }
+
+        let mut cmd = Command::new(program);
+        cmd.args(shell_args)
             .arg(actual_command)
-            .current_dir(work_dir.unwrap_or(".".to_string()))
+            .current_dir(work_dir)
+            .envs(env)
+            .stdin(if stdin.is_some() {
+                std::process::Stdio::piped()
+            } else {
+                std::process::Stdio::null()
+            })
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
-            .spawn()
-            .map_err(|e| {
+            .kill_on_drop(true);
+
+        // Run the command in its own process group so it can be killed along with its children
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let started = Instant::now();
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("SHELL_NOT_FOUND"),
+                    Content::text(format!("Cannot run {}, is it installed? {}", program, e)),
+                ]));
+            }
+            Err(e) => {
                 error!("Failed to run command: {}", e);
-                McpError::internal_error(
+                return Err(McpError::internal_error(
                     "Failed to run command",
                     Some(json!({
                         "command": command_clone,
                         "error": e.to_string()
                     })),
-                )
-            })?;
+                ));
+            }
+        };
+
+        if let (Some(input), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+            // Written while the output is read, so commands can't block on a full output pipe.
+            // Dropping the handle closes stdin once everything is written.
+            tokio::spawn(async move {
+                let _ = child_stdin.write_all(input.as_bytes()).await;
+            });
+        }
 
         #[allow(clippy::unwrap_used)]
         let stdout = child.stdout.take().unwrap();
@@ -75,21 +594,42 @@ impl LocalTools {
         let mut stdout_reader = BufReader::new(stdout);
         let mut stderr_reader = BufReader::new(stderr);
 
-        let mut stdout_buf = String::new();
-        let mut stderr_buf = String::new();
-        let mut result = String::new();
+        let mut stdout_buf = Vec::new();
+        let mut stderr_buf = Vec::new();
+        let mut stdout_output = String::new();
+        let mut stderr_output = String::new();
+        let mut stdout_open = true;
+        let mut stderr_open = true;
         let progress_id = Uuid::new_v4();
 
-        // Read from both streams concurrently
-        loop {
+        let deadline = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+        tokio::pin!(deadline);
+        let mut interruption: Option<CommandInterruption> = None;
+
+        // Read from both streams concurrently, until both are closed
+        while stdout_open || stderr_open {
             tokio::select! {
-                Ok(n) = stderr_reader.read_line(&mut stderr_buf) => {
-                    if n == 0 {
-                        break;
+                _ = &mut deadline => {
+                    interruption = timeout.map(CommandInterruption::TimedOut);
+                    break;
+                }
+                _ = ct.cancelled() => {
+                    interruption = Some(CommandInterruption::Cancelled);
+                    break;
+                }
+                read = stderr_reader.read_until(b'\n', &mut stderr_buf), if stderr_open => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        stderr_open = false;
+                        continue;
                     }
-                    let line = stderr_buf.trim_end_matches('\n').to_string();
+                    let line = String::from_utf8_lossy(&stderr_buf).trim_end_matches('\n').to_string();
                     stderr_buf.clear();
-                    result.push_str(&format!("{}\n", line));
+                    stderr_output.push_str(&format!("{}\n", line));
                     // Send notification but continue processing
                     let _ = peer.notify_progress(ProgressNotificationParam {
                         progress_token: ProgressToken(NumberOrString::Number(0)),
@@ -98,16 +638,18 @@ impl LocalTools {
                         message: Some(serde_json::to_string(&ToolCallResultProgress {
                             id: progress_id,
                             message: line,
+                            raw: false,
                         }).unwrap_or_default()),
                     }).await;
                 }
-                Ok(n) = stdout_reader.read_line(&mut stdout_buf) => {
-                    if n == 0 {
-                        break;
+                read = stdout_reader.read_until(b'\n', &mut stdout_buf), if stdout_open => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        stdout_open = false;
+                        continue;
                     }
-                    let line = stdout_buf.trim_end_matches('\n').to_string();
+                    let line = String::from_utf8_lossy(&stdout_buf).trim_end_matches('\n').to_string();
                     stdout_buf.clear();
-                    result.push_str(&format!("{}\n", line));
+                    stdout_output.push_str(&format!("{}\n", line));
                     // Send notification but continue processing
                     // skip if message is empty
                     if line.is_empty() {
@@ -120,18 +662,39 @@ impl LocalTools {
                         message: Some(serde_json::to_string(&ToolCallResultProgress {
                             id: progress_id,
                             message: format!("{}\n", line),
+                            raw: false,
                         }).unwrap_or_default()),
                     }).await;
                 }
-                else => break,
             }
         }
 
-        // Wait for the process to complete
-        let exit_code = child
-            .wait()
-            .await
-            .map_err(|e| {
+        // Wait for the process to complete, still honoring the timeout and cancellation
+        let exit_status = if interruption.is_none() {
+            tokio::select! {
+                status = child.wait() => Some(status),
+                _ = &mut deadline => {
+                    interruption = timeout.map(CommandInterruption::TimedOut);
+                    None
+                }
+                _ = ct.cancelled() => {
+                    interruption = Some(CommandInterruption::Cancelled);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut outcome = CommandOutcome {
+            stdout: stdout_output,
+            stderr: stderr_output,
+            ..Default::default()
+        };
+        if interruption.is_some() {
+            kill_process_group(&mut child).await;
+        } else if let Some(exit_status) = exit_status {
+            let exit_status = exit_status.map_err(|e| {
                 error!("Failed to wait for command: {}", e);
                 McpError::internal_error(
                     "Failed to wait for command",
@@ -140,59 +703,306 @@ impl LocalTools {
                         "error": e.to_string()
                     })),
                 )
-            })?
-            .code()
-            .unwrap_or(-1);
-
-        if exit_code != 0 {
-            result.push_str(&format!("Command exited with code {}\n", exit_code));
+            })?;
+            outcome.exit_code = exit_status.code();
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::ExitStatusExt;
+                outcome.signal = exit_status.signal();
+            }
         }
+        outcome.interruption = interruption;
 
-        let output_lines = result.lines().collect::<Vec<_>>();
+        self.command_result(outcome, started, None)
+    }
 
-        result = if output_lines.len() >= MAX_LINES {
-            // Create a output file to store the full output
-            let output_file = format!(
-                "command.output.{:06x}.txt",
-                rand::rng().random_range(0..=0xFFFFFF)
-            );
-            let output_file_path =
-                LocalStore::write_session_data(&output_file, &result).map_err(|e| {
-                    error!("Failed to write session data to {}: {}", output_file, e);
-                    McpError::internal_error(
-                        "Failed to write session data",
-                        Some(json!({ "error": e.to_string() })),
-                    )
-                })?;
+    #[tool(description = READ_COMMAND_OUTPUT_DESCRIPTION)]
+    pub fn read_command_output(
+        &self,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_ID_PARAM_DESCRIPTION)]
+        output_id: String,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_RANGE_PARAM_DESCRIPTION)]
+        view_range: Option<[i32; 2]>,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_GREP_PARAM_DESCRIPTION)]
+        grep: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        const MAX_LINES: usize = 300;
 
-            format!(
-                "Showing the last {} / {} output lines. Full output saved to {}\n...\n{}",
-                MAX_LINES,
-                output_lines.len(),
-                output_file_path,
-                output_lines
-                    .into_iter()
-                    .rev()
-                    .take(MAX_LINES)
-                    .rev()
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            )
-        } else {
-            result
+        let output = match load_output(&output_id) {
+            Ok(output) => output,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("OUTPUT_NOT_FOUND"),
+                    Content::text(e),
+                ]));
+            }
         };
 
-        if result.is_empty() {
-            return Ok(CallToolResult::success(vec![Content::text("No output")]));
+        let (first, last) = match view_range {
+            None => (1, None),
+            Some([start, -1]) if start >= 1 => (start as usize, None),
+            Some([start, end]) if start >= 1 && end >= start => {
+                (start as usize, Some(end as usize))
+            }
+            Some([start, end]) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_RANGE"),
+                    Content::text(format!(
+                        "Invalid line range [{}, {}], lines start at 1 and the end must be -1 or at least the start",
+                        start, end
+                    )),
+                ]));
+            }
+        };
+        // Outputs are saved redacted, so the pattern is matched as sent, placeholders included
+        let filter = match grep.as_deref().map(Regex::new).transpose() {
+            Ok(filter) => filter,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_PATTERN"),
+                    Content::text(format!("Invalid regular expression: {}", e)),
+                ]));
+            }
+        };
+
+        let selected = select_lines(&output, first, last, filter.as_ref(), MAX_LINES);
+        let mut result = match &filter {
+            Some(filter) => format!(
+                "Output {} ({} lines, {} lines matching {}):\n",
+                output_id,
+                selected.total_lines,
+                selected.matching_lines,
+                filter.as_str()
+            ),
+            None => format!("Output {} ({} lines):\n", output_id, selected.total_lines),
+        };
+        result.push_str(
+            &selected
+                .lines
+                .iter()
+                .map(|(number, line)| format!("{:6}: {}", number, line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        if selected.matching_lines > selected.lines.len() {
+            result.push_str(&format!(
+                "\n...\n[Only the first {} lines are shown, use view_range to read further]",
+                MAX_LINES
+            ));
+        } else if selected.lines.is_empty() && filter.is_some() {
+            result.push_str("No matching lines");
+        } else if selected.lines.is_empty() {
+            result.push_str("No lines in this range");
         }
 
-        let redacted_output = self.secret_manager.redact_and_store_secrets(&result, None);
+        // Outputs are saved redacted, redact again for outputs saved by older versions
+        Ok(CallToolResult::success(vec![Content::text(
+            self.secret_manager.redact_and_store_secrets(&result, None),
+        )]))
+    }
+
+    #[tool(description = START_BACKGROUND_DESCRIPTION)]
+    pub fn start_background(
+        &self,
+        #[tool(param)]
+        #[schemars(description = COMMAND_PARAM_DESCRIPTION)]
+        command: String,
+        #[tool(param)]
+        #[schemars(description = WORK_DIR_PARAM_DESCRIPTION)]
+        work_dir: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        // Restore secrets in the command before execution
+        let actual_command = self.secret_manager.restore_secrets_in_string(&command);
+
+        if let Some(blocked) = self.check_guardrails([actual_command.as_str()], work_dir.as_deref())
+        {
+            return Ok(blocked);
+        }
+
+        match self
+            .process_registry
+            .start(&command, &actual_command, work_dir)
+        {
+            Ok(process) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Started background process {} (pid {})\nUse read_process_output with process_id \"{}\" to check its output",
+                process.id,
+                process
+                    .pid
+                    .map(|pid| pid.to_string())
+                    .unwrap_or("unknown".to_string()),
+                process.id
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("START_ERROR"),
+                Content::text(e),
+            ])),
+        }
+    }
+
+    #[tool(description = LIST_PROCESSES_DESCRIPTION)]
+    pub fn list_processes(&self) -> Result<CallToolResult, McpError> {
+        let processes = self.process_registry.list();
+
+        if processes.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "No background processes",
+            )]));
+        }
+
+        let result = processes
+            .iter()
+            .map(|process| {
+                format!(
+                    "{} | pid {} | {} | up {}s | {} output lines | {}",
+                    process.id,
+                    process
+                        .pid
+                        .map(|pid| pid.to_string())
+                        .unwrap_or("unknown".to_string()),
+                    process.status(),
+                    process.uptime().as_secs(),
+                    process.total_lines(),
+                    process.command
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let redacted_result = self.secret_manager.redact_and_store_secrets(&result, None);
 
         Ok(CallToolResult::success(vec![Content::text(
-            &redacted_output,
+            &redacted_result,
         )]))
     }
 
+    #[tool(description = READ_PROCESS_OUTPUT_DESCRIPTION)]
+    pub fn read_process_output(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_OFFSET_PARAM_DESCRIPTION)]
+        offset: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = OUTPUT_LIMIT_PARAM_DESCRIPTION)]
+        limit: Option<usize>,
+    ) -> Result<CallToolResult, McpError> {
+        const MAX_LINES: usize = 300;
+
+        let limit = limit.unwrap_or(100).clamp(1, MAX_LINES);
+
+        let output = match self
+            .process_registry
+            .read_output(&process_id, offset, limit)
+        {
+            Ok(output) => output,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("PROCESS_NOT_FOUND"),
+                    Content::text(e),
+                ]));
+            }
+        };
+
+        let mut result = format!(
+            "Process {} is {}\nShowing output lines {}-{}, next offset: {}\n",
+            process_id, output.status, output.start_offset, output.next_offset, output.next_offset
+        );
+        if output.dropped_lines > 0 {
+            result.push_str(&format!(
+                "{} older lines were dropped from the output buffer\n",
+                output.dropped_lines
+            ));
+        }
+        if let Some(log_path) = output.log_path {
+            result.push_str(&format!("Buffered output saved to {}\n", log_path));
+        }
+        if output.text.is_empty() {
+            result.push_str("No new output");
+        } else {
+            result.push_str(&output.text);
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(result)]))
+    }
+
+    #[tool(description = SEND_PROCESS_INPUT_DESCRIPTION)]
+    pub async fn send_process_input(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+        #[tool(param)]
+        #[schemars(description = PROCESS_INPUT_PARAM_DESCRIPTION)]
+        input: String,
+        #[tool(param)]
+        #[schemars(description = NO_NEWLINE_PARAM_DESCRIPTION)]
+        no_newline: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.process_registry.get(&process_id).is_none() {
+            return Ok(CallToolResult::error(vec![
+                Content::text("PROCESS_NOT_FOUND"),
+                Content::text(format!("No background process with id {}", process_id)),
+            ]));
+        }
+
+        // Restore secrets in the input before sending it
+        let mut actual_input = self.secret_manager.restore_secrets_in_string(&input);
+        if !no_newline.unwrap_or(false) {
+            actual_input.push('\n');
+        }
+
+        match self
+            .process_registry
+            .send_input(&process_id, &actual_input)
+            .await
+        {
+            Ok(()) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Sent input to process {}",
+                process_id
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("WRITE_ERROR"),
+                Content::text(e),
+            ])),
+        }
+    }
+
+    #[tool(description = STOP_PROCESS_DESCRIPTION)]
+    pub async fn stop_process(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PROCESS_ID_PARAM_DESCRIPTION)]
+        process_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        let was_running = match self.process_registry.get(&process_id) {
+            Some(process) => process.status() == ProcessStatus::Running,
+            None => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("PROCESS_NOT_FOUND"),
+                    Content::text(format!("No background process with id {}", process_id)),
+                ]));
+            }
+        };
+
+        match self.process_registry.stop(&process_id).await {
+            Ok(process) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "{} process {}, it {}",
+                if was_running { "Stopped" } else { "Removed" },
+                process.id,
+                process.status()
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("PROCESS_NOT_FOUND"),
+                Content::text(e),
+            ])),
+        }
+    }
+
     #[tool(description = VIEW_DESCRIPTION)]
     pub fn view(
         &self,
@@ -202,10 +1012,30 @@ impl LocalTools {
         #[tool(param)]
         #[schemars(description = VIEW_RANGE_PARAM_DESCRIPTION)]
         view_range: Option<[i32; 2]>,
+        #[tool(param)]
+        #[schemars(description = HEX_RANGE_PARAM_DESCRIPTION)]
+        hex_range: Option<[u64; 2]>,
+        #[tool(param)]
+        #[schemars(description = DEPTH_PARAM_DESCRIPTION)]
+        depth: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_HIDDEN_PARAM_DESCRIPTION)]
+        include_hidden: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = RESPECT_GITIGNORE_PARAM_DESCRIPTION)]
+        respect_gitignore: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
         const MAX_LINES: usize = 300;
+        const MAX_TREE_DEPTH: usize = 10;
+        // Files up to this size are read to the end to count their lines
+        const MAX_LINE_COUNT_BYTES: u64 = 64 * 1024 * 1024;
 
-        let path_obj = Path::new(&path);
+        let resolved_path = match self.resolve_path(&path) {
+            Ok(resolved_path) => resolved_path,
+            Err(outside) => return Ok(outside),
+        };
+
+        let path_obj = resolved_path.as_path();
 
         if !path_obj.exists() {
             return Ok(CallToolResult::error(vec![
@@ -215,49 +1045,22 @@ impl LocalTools {
         }
 
         if path_obj.is_dir() {
-            // List directory contents
-            match fs::read_dir(&path) {
-                Ok(entries) => {
-                    let mut result = format!("Directory listing for \"{}\":\n", path);
-                    let mut items: Vec<_> = entries.collect();
-                    items.sort_by(|a, b| match (a, b) {
-                        (Ok(a_entry), Ok(b_entry)) => {
-                            match (
-                                a_entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false),
-                                b_entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false),
-                            ) {
-                                (true, false) => std::cmp::Ordering::Less,
-                                (false, true) => std::cmp::Ordering::Greater,
-                                _ => a_entry.file_name().cmp(&b_entry.file_name()),
-                            }
-                        }
-                        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
-                        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
-                        (Err(_), Err(_)) => std::cmp::Ordering::Equal,
-                    });
-
-                    for (i, entry) in items.iter().enumerate() {
-                        let is_last = i == items.len() - 1;
-                        let prefix = if is_last { "└── " } else { "├── " };
-                        match entry {
-                            Ok(entry) => {
-                                let suffix = match entry.file_type() {
-                                    Ok(ft) if ft.is_dir() => "/",
-                                    Ok(_) => "",
-                                    Err(_) => "?",
-                                };
-                                result.push_str(&format!(
-                                    "{}{}{}\n",
-                                    prefix,
-                                    entry.file_name().to_string_lossy(),
-                                    suffix
-                                ));
-                            }
-                            Err(e) => {
-                                result.push_str(&format!("Error reading entry: {}\n", e));
-                            }
-                        }
-                    }
+            let options = TreeOptions {
+                depth: depth.unwrap_or(2).clamp(1, MAX_TREE_DEPTH),
+                include_hidden: include_hidden.unwrap_or(true),
+                respect_gitignore: respect_gitignore.unwrap_or(true),
+                max_entries: MAX_LINES,
+            };
+            // Subdirectories outside the workspace (like ~/.stakpak) are only counted
+            let can_enter = |dir: &Path| self.workspace.resolve(&dir.to_string_lossy()).is_ok();
+            match read_tree(path_obj, &options, can_enter) {
+                Ok(tree) => {
+                    let result = format!(
+                        "Directory tree for \"{}\" (depth {}):\n{}",
+                        path,
+                        options.depth,
+                        render_tree(&tree)
+                    );
                     Ok(CallToolResult::success(vec![Content::text(result)]))
                 }
                 Err(e) => Ok(CallToolResult::error(vec![
@@ -266,106 +1069,289 @@ impl LocalTools {
                 ])),
             }
         } else {
-            // Read file contents
-            match fs::read_to_string(&path) {
-                Ok(content) => {
-                    let result = if let Some([start, end]) = view_range {
-                        let lines: Vec<&str> = content.lines().collect();
-                        let start_idx = if start <= 0 { 0 } else { (start - 1) as usize };
-                        let end_idx = if end == -1 {
-                            lines.len()
-                        } else {
-                            std::cmp::min(end as usize, lines.len())
-                        };
-
-                        if start_idx >= lines.len() {
-                            return Ok(CallToolResult::error(vec![
-                                Content::text("INVALID_RANGE"),
-                                Content::text(format!(
-                                    "Start line {} is beyond file length {}",
-                                    start,
-                                    lines.len()
-                                )),
-                            ]));
-                        }
+            let size = fs::metadata(path_obj)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            let kind = match FileKind::of_file(path_obj) {
+                Ok(kind) => kind,
+                Err(e) => {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("READ_ERROR"),
+                        Content::text(format!("Cannot read file: {}", e)),
+                    ]));
+                }
+            };
 
-                        let selected_lines = &lines[start_idx..end_idx];
-                        if selected_lines.len() <= MAX_LINES {
-                            format!(
-                                "File: {} (lines {}-{})\n{}",
-                                path,
-                                start_idx + 1,
-                                end_idx,
-                                selected_lines
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(i, line)| format!("{:3}: {}", start_idx + i + 1, line))
-                                    .collect::<Vec<_>>()
-                                    .join("\n")
-                            )
-                        } else {
-                            // truncate the extra lines
-                            let selected_lines =
-                                selected_lines.iter().take(MAX_LINES).collect::<Vec<_>>();
-
-                            format!(
-                                "File: {} (showing lines {}-{}, only the first {} lines of your view range)\n{}\n...",
-                                path,
-                                start_idx + 1,
-                                start_idx + 1 + MAX_LINES,
-                                MAX_LINES,
-                                selected_lines
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(i, line)| format!("{:4}: {}", start_idx + i + 1, line))
-                                    .collect::<Vec<_>>()
-                                    .join("\n")
-                            )
-                        }
+            if let Some([start, end]) = hex_range {
+                if end <= start {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("INVALID_RANGE"),
+                        Content::text(format!(
+                            "hex_range end {} must be after its start {}",
+                            end, start
+                        )),
+                    ]));
+                }
+                // Secrets can't be redacted once split across hexdump rows, text files are
+                // only shown as text where they are
+                if matches!(kind, FileKind::Text { .. }) {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("TEXT_FILE"),
+                        Content::text(format!(
+                            "File: {} is a text file, hex_range is only available for binary files. View it without hex_range instead.",
+                            path
+                        )),
+                    ]));
+                }
+                let shown_end = end.min(size).min(start.saturating_add(MAX_HEXDUMP_BYTES));
+                return match hexdump(path_obj, start, end) {
+                    Ok(dump) => {
+                        let result = format!(
+                            "File: {} ({} bytes, bytes {}-{})\n{}",
+                            path, size, start, shown_end, dump
+                        );
+                        Ok(CallToolResult::success(vec![Content::text(
+                            self.secret_manager
+                                .redact_and_store_secrets(&result, Some(&path)),
+                        )]))
+                    }
+                    Err(e) => Ok(CallToolResult::error(vec![
+                        Content::text("READ_ERROR"),
+                        Content::text(format!("Cannot read file: {}", e)),
+                    ])),
+                };
+            }
+
+            let (encoding, encoding_note) = match kind {
+                FileKind::Binary { description } => {
+                    return Ok(CallToolResult::success(vec![Content::text(format!(
+                        "File: {} is binary ({}, {} bytes), its content is not shown. Use hex_range to view its bytes as a hexdump, e.g. [0, 512]",
+                        path, description, size
+                    ))]));
+                }
+                FileKind::Text { encoding, .. } if encoding.name() == "UTF-8" => {
+                    (encoding, String::new())
+                }
+                FileKind::Text { encoding, guessed } => (
+                    encoding,
+                    format!(
+                        ", decoded from {}{}",
+                        encoding.name(),
+                        if guessed { " (detected)" } else { "" }
+                    ),
+                ),
+            };
+
+            // Read one line more than shown to know whether the output is truncated
+            let (start_idx, wanted_lines) = match view_range {
+                Some([start, end]) => {
+                    let start_idx = if start <= 0 { 0 } else { (start - 1) as usize };
+                    if end != -1 && (end as usize) <= start_idx {
+                        return Ok(CallToolResult::error(vec![
+                            Content::text("INVALID_RANGE"),
+                            Content::text(format!(
+                                "End line {} is before start line {}",
+                                end, start
+                            )),
+                        ]));
+                    }
+                    let wanted = if end == -1 {
+                        usize::MAX
                     } else {
-                        let lines: Vec<&str> = content.lines().collect();
-                        if lines.len() <= MAX_LINES {
-                            format!(
-                                "File: {} ({} lines)\n{}",
-                                path,
-                                lines.len(),
-                                lines
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(i, line)| format!("{:3}: {}", i + 1, line))
-                                    .collect::<Vec<_>>()
-                                    .join("\n")
-                            )
-                        } else {
-                            // truncate the extra lines
-                            let selected_lines = lines.iter().take(MAX_LINES).collect::<Vec<_>>();
-                            format!(
-                                "File: {} (showing {} / {} lines)\n{}\n...",
-                                path,
-                                MAX_LINES,
-                                lines.len(),
-                                selected_lines
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(i, line)| format!("{:3}: {}", i + 1, line))
-                                    .collect::<Vec<_>>()
-                                    .join("\n")
-                            )
-                        }
+                        end as usize - start_idx
                     };
+                    (start_idx, wanted)
+                }
+                None => (0, usize::MAX),
+            };
+            // Counting the lines of huge files would read all of them
+            let count_all = view_range.is_none() && size <= MAX_LINE_COUNT_BYTES;
+            let read = match read_lines(
+                path_obj,
+                encoding,
+                start_idx + 1,
+                wanted_lines.min(MAX_LINES + 1),
+                count_all,
+            ) {
+                Ok(read) => read,
+                Err(e) => {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("READ_ERROR"),
+                        Content::text(format!("Cannot read file: {}", e)),
+                    ]));
+                }
+            };
+            let numbered = |lines: &[(usize, String)], width: usize| {
+                lines
+                    .iter()
+                    .take(MAX_LINES)
+                    .map(|(number, line)| format!("{:width$}: {}", number, line, width = width))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            let truncated = read.lines.len() > MAX_LINES && wanted_lines > MAX_LINES;
 
-                    let redacted_result = self
-                        .secret_manager
-                        .redact_and_store_secrets(&result, Some(&path));
-                    Ok(CallToolResult::success(vec![Content::text(
-                        &redacted_result,
-                    )]))
+            let mut result = if view_range.is_some() {
+                if read.lines.is_empty() {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("INVALID_RANGE"),
+                        Content::text(format!(
+                            "Start line {} is beyond file length {}",
+                            start_idx + 1,
+                            read.total_lines.unwrap_or(0)
+                        )),
+                    ]));
                 }
-                Err(e) => Ok(CallToolResult::error(vec![
-                    Content::text("READ_ERROR"),
-                    Content::text(format!("Cannot read file: {}", e)),
-                ])),
+                if !truncated {
+                    format!(
+                        "File: {} (lines {}-{}{})\n{}",
+                        path,
+                        start_idx + 1,
+                        start_idx + read.lines.len(),
+                        encoding_note,
+                        numbered(&read.lines, 3)
+                    )
+                } else {
+                    format!(
+                        "File: {} (showing lines {}-{}, only the first {} lines of your view range{})\n{}\n...",
+                        path,
+                        start_idx + 1,
+                        start_idx + MAX_LINES,
+                        MAX_LINES,
+                        encoding_note,
+                        numbered(&read.lines, 4)
+                    )
+                }
+            } else if !truncated {
+                format!(
+                    "File: {} ({} lines{})\n{}",
+                    path,
+                    read.lines.len(),
+                    encoding_note,
+                    numbered(&read.lines, 3)
+                )
+            } else {
+                let total = match read.total_lines {
+                    Some(total) => format!("{} lines", total),
+                    None => format!("a {} bytes file, use view_range to read further", size),
+                };
+                format!(
+                    "File: {} (showing {} / {}{})\n{}\n...",
+                    path,
+                    MAX_LINES,
+                    total,
+                    encoding_note,
+                    numbered(&read.lines, 3)
+                )
+            };
+            if read.cut_lines > 0 {
+                result.push_str(&format!(
+                    "\n[{} long lines were cut at {} characters]",
+                    read.cut_lines, MAX_LINE_CHARS
+                ));
             }
+
+            let redacted_result = self
+                .secret_manager
+                .redact_and_store_secrets(&result, Some(&path));
+            Ok(CallToolResult::success(vec![Content::text(
+                &redacted_result,
+            )]))
+        }
+    }
+
+    #[tool(description = GLOB_DESCRIPTION)]
+    pub fn glob(
+        &self,
+        #[tool(param)]
+        #[schemars(description = GLOB_PATTERN_PARAM_DESCRIPTION)]
+        pattern: String,
+        #[tool(param)]
+        #[schemars(description = SEARCH_PATH_PARAM_DESCRIPTION)]
+        path: Option<String>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_IGNORED_PARAM_DESCRIPTION)]
+        include_ignored: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = GLOB_LIMIT_PARAM_DESCRIPTION)]
+        limit: Option<usize>,
+    ) -> Result<CallToolResult, McpError> {
+        const MAX_FILES: usize = 1000;
+
+        let path = path.unwrap_or_else(|| ".".to_string());
+        let root = match self.search_root(&path) {
+            Ok(root) => root,
+            Err(error) => return Ok(error),
+        };
+        let limit = limit.unwrap_or(200).clamp(1, MAX_FILES);
+        match search::glob(
+            &path,
+            &root,
+            &self.workspace,
+            &pattern,
+            include_ignored.unwrap_or(false),
+            limit,
+        ) {
+            Ok(result) => Ok(self.search_result(&result)),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("INVALID_PATTERN"),
+                Content::text(e),
+            ])),
+        }
+    }
+
+    #[tool(description = GREP_DESCRIPTION)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn grep(
+        &self,
+        #[tool(param)]
+        #[schemars(description = GREP_PATTERN_PARAM_DESCRIPTION)]
+        pattern: String,
+        #[tool(param)]
+        #[schemars(description = SEARCH_PATH_PARAM_DESCRIPTION)]
+        path: Option<String>,
+        #[tool(param)]
+        #[schemars(description = CASE_INSENSITIVE_PARAM_DESCRIPTION)]
+        case_insensitive: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = CONTEXT_LINES_PARAM_DESCRIPTION)]
+        context_lines: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = GREP_INCLUDE_PARAM_DESCRIPTION)]
+        include: Option<String>,
+        #[tool(param)]
+        #[schemars(description = FILE_TYPE_PARAM_DESCRIPTION)]
+        file_type: Option<String>,
+        #[tool(param)]
+        #[schemars(description = INCLUDE_IGNORED_PARAM_DESCRIPTION)]
+        include_ignored: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = MAX_RESULTS_PARAM_DESCRIPTION)]
+        max_results: Option<usize>,
+    ) -> Result<CallToolResult, McpError> {
+        const MAX_RESULTS: usize = 500;
+
+        let path = path.unwrap_or_else(|| ".".to_string());
+        let root = match self.search_root(&path) {
+            Ok(root) => root,
+            Err(error) => return Ok(error),
+        };
+        // Search for the secrets behind placeholders the agent copied from earlier output
+        let options = GrepOptions {
+            pattern: self.secret_manager.restore_secrets_in_string(&pattern),
+            case_insensitive: case_insensitive.unwrap_or(false),
+            context: context_lines.unwrap_or(0),
+            include,
+            file_type,
+            include_ignored: include_ignored.unwrap_or(false),
+            max_results: max_results.unwrap_or(100).clamp(1, MAX_RESULTS),
+        };
+        match search::grep(&path, &root, &self.workspace, &options) {
+            Ok(result) => Ok(self.search_result(&result)),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("INVALID_PATTERN"),
+                Content::text(e),
+            ])),
         }
     }
 
@@ -385,7 +1371,12 @@ impl LocalTools {
         #[schemars(description = REPLACE_ALL_PARAM_DESCRIPTION)]
         replace_all: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
-        let path_obj = Path::new(&path);
+        let resolved_path = match self.resolve_path(&path) {
+            Ok(resolved_path) => resolved_path,
+            Err(outside) => return Ok(outside),
+        };
+
+        let path_obj = resolved_path.as_path();
 
         if !path_obj.exists() {
             return Ok(CallToolResult::error(vec![
@@ -405,7 +1396,7 @@ impl LocalTools {
         let actual_old_str = self.secret_manager.restore_secrets_in_string(&old_str);
         let actual_new_str = self.secret_manager.restore_secrets_in_string(&new_str);
 
-        match fs::read_to_string(&path) {
+        match fs::read_to_string(&resolved_path) {
             Ok(content) => {
                 let matches: Vec<_> = content.match_indices(&actual_old_str).collect();
 
@@ -418,11 +1409,19 @@ impl LocalTools {
                     ])),
                     (1, _) => {
                         let new_content = content.replace(&actual_old_str, &actual_new_str);
-                        match fs::write(&path, new_content) {
-                            Ok(_) => Ok(CallToolResult::success(vec![Content::text(format!(
-                                "Successfully replaced text in {}",
-                                path
-                            ))])),
+                        match fs::write(&resolved_path, &new_content) {
+                            Ok(_) => {
+                                journal_edit(
+                                    "str_replace",
+                                    &resolved_path,
+                                    Some(content),
+                                    new_content,
+                                );
+                                Ok(CallToolResult::success(vec![Content::text(format!(
+                                    "Successfully replaced text in {}",
+                                    path
+                                ))]))
+                            }
                             Err(e) => Ok(CallToolResult::error(vec![
                                 Content::text("WRITE_ERROR"),
                                 Content::text(format!("Cannot write to file: {}", e)),
@@ -431,11 +1430,19 @@ impl LocalTools {
                     }
                     (n, Some(true)) => {
                         let new_content = content.replace(&actual_old_str, &actual_new_str);
-                        match fs::write(&path, new_content) {
-                            Ok(_) => Ok(CallToolResult::success(vec![Content::text(format!(
-                                "Successfully replaced {} occurrences of text in {}",
-                                n, path
-                            ))])),
+                        match fs::write(&resolved_path, &new_content) {
+                            Ok(_) => {
+                                journal_edit(
+                                    "str_replace",
+                                    &resolved_path,
+                                    Some(content),
+                                    new_content,
+                                );
+                                Ok(CallToolResult::success(vec![Content::text(format!(
+                                    "Successfully replaced {} occurrences of text in {}",
+                                    n, path
+                                ))]))
+                            }
                             Err(e) => Ok(CallToolResult::error(vec![
                                 Content::text("WRITE_ERROR"),
                                 Content::text(format!("Cannot write to file: {}", e)),
@@ -468,7 +1475,12 @@ impl LocalTools {
         #[schemars(description = FILE_TEXT_PARAM_DESCRIPTION)]
         file_text: String,
     ) -> Result<CallToolResult, McpError> {
-        let path_obj = Path::new(&path);
+        let resolved_path = match self.resolve_path(&path) {
+            Ok(resolved_path) => resolved_path,
+            Err(outside) => return Ok(outside),
+        };
+
+        let path_obj = resolved_path.as_path();
 
         if path_obj.exists() {
             return Ok(CallToolResult::error(vec![
@@ -492,9 +1504,10 @@ impl LocalTools {
         // Restore secrets in the file content before writing
         let actual_file_text = self.secret_manager.restore_secrets_in_string(&file_text);
 
-        match fs::write(&path, actual_file_text) {
+        match fs::write(&resolved_path, &actual_file_text) {
             Ok(_) => {
-                let lines = fs::read_to_string(&path)
+                journal_edit("create", &resolved_path, None, actual_file_text);
+                let lines = fs::read_to_string(&resolved_path)
                     .map(|content| content.lines().count())
                     .unwrap_or(0);
                 Ok(CallToolResult::success(vec![Content::text(format!(
@@ -509,6 +1522,213 @@ impl LocalTools {
         }
     }
 
+    #[tool(description = APPLY_PATCH_DESCRIPTION)]
+    pub fn apply_patch(
+        &self,
+        #[tool(param)]
+        #[schemars(description = PATCH_PARAM_DESCRIPTION)]
+        patch: Option<String>,
+        #[tool(param)]
+        #[schemars(description = PATCH_EDITS_PARAM_DESCRIPTION)]
+        edits: Option<Vec<PatchEdit>>,
+    ) -> Result<CallToolResult, McpError> {
+        // Restore secrets in the patch before matching it against the files
+        let (patches, unit) = match (patch, edits) {
+            (Some(patch), None) => {
+                let patch = self.secret_manager.restore_secrets_in_string(&patch);
+                match parse_unified_diff(&patch) {
+                    Ok(patches) => (patches, "hunk"),
+                    Err(e) => {
+                        return Ok(CallToolResult::error(vec![
+                            Content::text("INVALID_PATCH"),
+                            Content::text(format!("Cannot parse the patch: {}", e)),
+                        ]));
+                    }
+                }
+            }
+            (None, Some(edits)) if !edits.is_empty() => {
+                let edits = edits
+                    .into_iter()
+                    .map(|edit| PatchEdit {
+                        old_str: self.secret_manager.restore_secrets_in_string(&edit.old_str),
+                        new_str: self.secret_manager.restore_secrets_in_string(&edit.new_str),
+                        ..edit
+                    })
+                    .collect();
+                (group_edits(edits), "edit")
+            }
+            _ => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_PATCH"),
+                    Content::text("Provide either a unified diff in patch or a list of edits"),
+                ]));
+            }
+        };
+
+        // Validate every hunk before writing anything
+        let mut planned: Vec<PlannedWrite> = Vec::new();
+        let mut failures: Vec<(&str, String)> = Vec::new();
+        let mut hunks = 0;
+        for file in patches {
+            let resolved_path = match self.resolve_path(&file.path) {
+                Ok(resolved_path) => resolved_path,
+                Err(outside) => return Ok(outside),
+            };
+            hunks += file.edits.len();
+            // A file listed twice continues from its first set of edits
+            let existing = planned
+                .iter_mut()
+                .find(|write| write.resolved_path == resolved_path);
+            let before = match existing.as_ref() {
+                Some(write) => Ok(write.after.clone()),
+                None => fs::read_to_string(&resolved_path),
+            };
+            let creates = file.create
+                || file
+                    .edits
+                    .first()
+                    .is_some_and(|edit| edit.old_str.is_empty());
+            let before = match before {
+                Ok(_) if file.create && existing.is_none() => {
+                    failures.push(("FILE_EXISTS", format!("{}: File already exists", file.path)));
+                    continue;
+                }
+                Ok(content) => Some(content),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && creates => None,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    failures.push(("FILE_NOT_FOUND", format!("{}: File not found", file.path)));
+                    continue;
+                }
+                Err(e) => {
+                    failures.push((
+                        "READ_ERROR",
+                        format!("{}: Cannot read file: {}", file.path, e),
+                    ));
+                    continue;
+                }
+            };
+
+            match apply_edits(before.as_deref().unwrap_or_default(), &file.edits) {
+                Ok(after) => match existing {
+                    Some(write) => write.after = after,
+                    None => planned.push(PlannedWrite {
+                        path: file.path,
+                        resolved_path,
+                        before,
+                        after,
+                    }),
+                },
+                Err(errors) => {
+                    for (index, error) in errors {
+                        failures.push((
+                            error.code(),
+                            format!("{} {} {}: {}", file.path, unit, index + 1, error),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            let code = if failures.iter().all(|(code, _)| *code == failures[0].0) {
+                failures[0].0
+            } else {
+                "PATCH_FAILED"
+            };
+            let report = failures
+                .iter()
+                .map(|(code, message)| format!("- {} {}", code, message))
+                .collect::<Vec<_>>()
+                .join("\n");
+            return Ok(CallToolResult::error(vec![
+                Content::text(code),
+                Content::text(format!(
+                    "No files were changed, {} of the {} {}s failed:\n{}",
+                    failures.len(),
+                    hunks,
+                    unit,
+                    report
+                )),
+            ]));
+        }
+
+        let mut written: Vec<&PlannedWrite> = Vec::new();
+        for write in &planned {
+            let result = match write.resolved_path.parent() {
+                Some(parent) => fs::create_dir_all(parent),
+                None => Ok(()),
+            }
+            .and_then(|_| fs::write(&write.resolved_path, &write.after));
+            if let Err(e) = result {
+                roll_back(&written);
+                return Ok(CallToolResult::error(vec![
+                    Content::text("WRITE_ERROR"),
+                    Content::text(format!(
+                        "Cannot write to {}: {}. The files written before it were restored, no files were changed",
+                        write.path, e
+                    )),
+                ]));
+            }
+            written.push(write);
+        }
+
+        let files = planned
+            .iter()
+            .map(|write| match write.before {
+                Some(_) => write.path.clone(),
+                None => format!("{} (created)", write.path),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let file_count = planned.len();
+        for write in planned {
+            journal_edit(
+                "apply_patch",
+                &write.resolved_path,
+                write.before,
+                write.after,
+            );
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Successfully applied {} {}s to {} files: {}",
+            hunks, unit, file_count, files
+        ))]))
+    }
+
+    #[tool(description = UNDO_LAST_EDIT_DESCRIPTION)]
+    pub fn undo_last_edit(&self) -> Result<CallToolResult, McpError> {
+        match EditJournal::update_session(|journal| journal.undo_last(&self.workspace)) {
+            Ok(edit) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Reverted the {} of {} made at {}",
+                edit.tool,
+                edit.path.display(),
+                edit.timestamp.format("%Y-%m-%d %H:%M:%S")
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("UNDO_ERROR"),
+                Content::text(e),
+            ])),
+        }
+    }
+
+    #[tool(description = LIST_EDITS_DESCRIPTION)]
+    pub fn list_edits(&self) -> Result<CallToolResult, McpError> {
+        match EditJournal::load_session() {
+            Ok(journal) if journal.edits.is_empty() => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    "No file edits recorded in this session",
+                )]))
+            }
+            Ok(journal) => Ok(CallToolResult::success(vec![Content::text(
+                journal.summary(),
+            )])),
+            Err(e) => Ok(CallToolResult::error(vec![
+                Content::text("READ_ERROR"),
+                Content::text(e),
+            ])),
+        }
+    }
+
     #[tool(description = GENERATE_PASSWORD_DESCRIPTION)]
     pub async fn generate_password(
         &self,
@@ -552,20 +1772,124 @@ impl LocalTools {
     }
 }
 
-#[tool(tool_box)]
+/// How long the output of a PTY command is still read once it has exited
+const PTY_OUTPUT_DRAIN: Duration = Duration::from_millis(500);
+
+/// Stream the raw output of a PTY command as progress until it exits, is cancelled or times out
+async fn run_in_pty(
+    mut process: PtyProcess,
+    mut chunks: mpsc::Receiver<Vec<u8>>,
+    peer: &rmcp::Peer<RoleServer>,
+    ct: &CancellationToken,
+    timeout: Option<Duration>,
+) -> CommandOutcome {
+    let progress_id = Uuid::new_v4();
+    let mut decoder = ChunkDecoder::default();
+    let mut output = String::new();
+    let mut interruption = None;
+    let mut output_open = true;
+
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending::<()>().await,
+        }
+    };
+    tokio::pin!(deadline);
+
+    // The output can end before the process does, if it closes the terminal, and the process
+    // can exit before the output ends, if a background child keeps the terminal open
+    let exit_status = loop {
+        tokio::select! {
+            _ = &mut deadline => {
+                interruption = timeout.map(CommandInterruption::TimedOut);
+                break None;
+            }
+            _ = ct.cancelled() => {
+                interruption = Some(CommandInterruption::Cancelled);
+                break None;
+            }
+            chunk = chunks.recv(), if output_open => {
+                let Some(chunk) = chunk else {
+                    output_open = false;
+                    continue;
+                };
+                let text = decoder.decode(&chunk);
+                if text.is_empty() {
+                    continue;
+                }
+                output.push_str(&text);
+                let _ = peer.notify_progress(ProgressNotificationParam {
+                    progress_token: ProgressToken(NumberOrString::Number(0)),
+                    progress: 50,
+                    total: Some(100),
+                    message: Some(serde_json::to_string(&ToolCallResultProgress {
+                        id: progress_id,
+                        message: text,
+                        raw: true,
+                    }).unwrap_or_default()),
+                }).await;
+            }
+            status = process.wait() => break Some(status),
+        }
+    };
+    if exit_status.is_some() && output_open {
+        // Take the output the process printed before exiting, without waiting on children
+        // that outlive it
+        let drain_deadline = tokio::time::Instant::now() + PTY_OUTPUT_DRAIN;
+        while let Ok(Some(chunk)) = tokio::time::timeout_at(drain_deadline, chunks.recv()).await {
+            output.push_str(&decoder.decode(&chunk));
+        }
+    }
+
+    let mut outcome = CommandOutcome {
+        // The agent gets the text the terminal shows, without the escape sequences
+        stdout: pty_command::terminal_text(&output),
+        interruption,
+        ..Default::default()
+    };
+    match exit_status {
+        Some(Ok(status)) => outcome.exit_code = Some(status.exit_code() as i32),
+        Some(Err(e)) => error!("Failed to wait for command: {}", e),
+        None => process.kill(),
+    }
+    outcome
+}
+
 impl ServerHandler for LocalTools {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_resources()
+                .enable_tools()
+                .build(),
             server_info: Implementation::from_build_env(),
             instructions: Some(
-                "This server provides local tools for file operations and command execution."
+                "This server provides local tools for file operations and command execution, and the session files as resources."
                     .to_string(),
             ),
         }
     }
 
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        Ok(ListResourcesResult::with_all_items(
+            resources::session_resources(),
+        ))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        resources::read_session_resource(&request.uri, &self.secret_manager)
+    }
+
     async fn initialize(
         &self,
         _request: InitializeRequestParam,
@@ -573,4 +1897,31 @@ impl ServerHandler for LocalTools {
     ) -> Result<InitializeResult, McpError> {
         Ok(self.get_info())
     }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(
+            Self::tool_box()
+                .list()
+                .into_iter()
+                .filter(|tool| self.tool_filter.allows(&tool.name))
+                .collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.tool_filter.allows(&request.name) {
+            return Ok(tool_disabled(&request.name));
+        }
+        Self::tool_box()
+            .call(ToolCallContext::new(self, request, context))
+            .await
+    }
 }
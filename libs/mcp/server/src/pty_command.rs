@@ -0,0 +1,205 @@
+use portable_pty::{
+    ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize, native_pty_system,
+};
+use regex::Regex;
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use tokio::sync::{mpsc, oneshot};
+
+/// Size of the terminal of PTY commands that don't ask for one, in columns and rows
+pub const DEFAULT_TERMINAL_SIZE: [u16; 2] = [120, 40];
+const MIN_TERMINAL_SIZE: [u16; 2] = [20, 5];
+const MAX_TERMINAL_SIZE: [u16; 2] = [500, 200];
+
+/// The `[columns, rows]` size asked for, or the default, within what terminals support
+pub fn terminal_size(size: Option<[u16; 2]>) -> [u16; 2] {
+    let [cols, rows] = size.unwrap_or(DEFAULT_TERMINAL_SIZE);
+    [
+        cols.clamp(MIN_TERMINAL_SIZE[0], MAX_TERMINAL_SIZE[0]),
+        rows.clamp(MIN_TERMINAL_SIZE[1], MAX_TERMINAL_SIZE[1]),
+    ]
+}
+
+/// A command running in a pseudo terminal, for programs that behave differently without a TTY
+pub struct PtyProcess {
+    exit: oneshot::Receiver<std::io::Result<ExitStatus>>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    #[cfg(unix)]
+    process_id: Option<u32>,
+    /// Keeps the terminal's input open until the process is dropped
+    _input_open: std::sync::mpsc::Sender<()>,
+    _master: Box<dyn MasterPty + Send>,
+}
+
+impl PtyProcess {
+    /// Spawn `command` in a terminal of `[columns, rows]`, typing `input` into it. Returns the
+    /// process and its raw output chunks, ANSI sequences included, which end once the terminal
+    /// has no more output.
+    pub fn spawn(
+        command: CommandBuilder,
+        [cols, rows]: [u16; 2],
+        input: Option<String>,
+    ) -> anyhow::Result<(Self, mpsc::Receiver<Vec<u8>>)> {
+        let pair = native_pty_system().openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        let mut child = pair.slave.spawn_command(command)?;
+        // The output only ends once every handle to the slave side is closed
+        drop(pair.slave);
+
+        let mut reader = pair.master.try_clone_reader()?;
+        let mut writer = pair.master.take_writer()?;
+        let killer = child.clone_killer();
+        #[cfg(unix)]
+        let process_id = child.process_id();
+
+        let (output_tx, output) = mpsc::channel(100);
+        std::thread::spawn(move || {
+            let mut buffer = vec![0u8; 4096];
+            loop {
+                match reader.read(&mut buffer) {
+                    // Reads fail instead of returning 0 on some platforms once the process exits
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if output_tx.blocking_send(buffer[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        let (exit_tx, exit) = oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = exit_tx.send(child.wait());
+        });
+
+        // Typed on its own thread, a command that doesn't read its input would block the write.
+        // Closing the writer sends an end of input, so it's only dropped with the process.
+        let (input_open, input_closed) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            if let Some(input) = input {
+                let _ = writer.write_all(input.as_bytes());
+                let _ = writer.flush();
+            }
+            let _ = input_closed.recv();
+        });
+
+        let process = Self {
+            exit,
+            killer,
+            #[cfg(unix)]
+            process_id,
+            _input_open: input_open,
+            _master: pair.master,
+        };
+        Ok((process, output))
+    }
+
+    /// Wait for the process to exit
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        (&mut self.exit).await.unwrap_or_else(|_| {
+            Err(std::io::Error::other(
+                "The process was lost before it exited",
+            ))
+        })
+    }
+
+    /// Kill the process along with every process it spawned
+    pub fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.process_id {
+            // The process leads its own session and process group, so its pid is the group id
+            // SAFETY: killpg only sends a signal, it takes no pointers and touches no memory
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        let _ = self.killer.kill();
+    }
+}
+
+/// Decodes a stream of output chunks, holding back the bytes of a character split across
+/// chunks
+#[derive(Default)]
+pub struct ChunkDecoder {
+    pending: Vec<u8>,
+}
+
+impl ChunkDecoder {
+    pub fn decode(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // An incomplete character at the end, wait for the rest of it
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let rest = self.pending.split_off(valid);
+        let text = String::from_utf8_lossy(&self.pending).to_string();
+        self.pending = rest;
+        text
+    }
+}
+
+/// The text a terminal would show for raw output: escape sequences removed, and lines
+/// rewritten after a carriage return (like progress bars) keep only their last version
+pub fn terminal_text(raw: &str) -> String {
+    static ESCAPE_SEQUENCE: OnceLock<Option<Regex>> = OnceLock::new();
+    let stripped = match ESCAPE_SEQUENCE.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)?|\x1b[@-_]").ok()
+    }) {
+        Some(escape_sequence) => escape_sequence.replace_all(raw, ""),
+        None => raw.into(),
+    };
+    stripped
+        .split('\n')
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            line.rsplit('\r').next().unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_size_is_clamped() {
+        assert_eq!(terminal_size(None), DEFAULT_TERMINAL_SIZE);
+        assert_eq!(terminal_size(Some([80, 24])), [80, 24]);
+        assert_eq!(terminal_size(Some([0, 0])), MIN_TERMINAL_SIZE);
+        assert_eq!(terminal_size(Some([u16::MAX, u16::MAX])), MAX_TERMINAL_SIZE);
+    }
+
+    #[test]
+    fn test_chunk_decoder_holds_back_split_characters() {
+        let mut decoder = ChunkDecoder::default();
+        let bytes = "café ✓".as_bytes();
+        // `é` is split across the first two chunks, `✓` across the last two
+        assert_eq!(decoder.decode(&bytes[..4]), "caf");
+        assert_eq!(decoder.decode(&bytes[4..7]), "é ");
+        assert_eq!(decoder.decode(&bytes[7..8]), "");
+        assert_eq!(decoder.decode(&bytes[8..]), "✓");
+        // Invalid bytes aren't held back
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_terminal_text() {
+        assert_eq!(
+            terminal_text("\x1b[1;32mok\x1b[0m\r\n\x1b]0;title\x07done"),
+            "ok\ndone"
+        );
+        // Progress bars rewriting their line keep their last version
+        assert_eq!(
+            terminal_text("Downloading 10%\rDownloading 50%\rDownloading 100%\r\nDone\n"),
+            "Downloading 100%\nDone\n"
+        );
+    }
+}
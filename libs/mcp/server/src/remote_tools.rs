@@ -1,5 +1,6 @@
 use rmcp::{
-    Error as McpError, RoleServer, ServerHandler, model::*, schemars, service::RequestContext, tool,
+    Error as McpError, RoleServer, ServerHandler, handler::server::tool::ToolCallContext, model::*,
+    schemars, service::RequestContext, tool,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -12,16 +13,20 @@ use stakpak_shared::models::indexing::IndexingStatus;
 use std::fs::{self};
 use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
 use tracing::{error, warn};
 
+use crate::resources;
 use crate::secret_manager::SecretManager;
 use crate::tool_descriptions::*;
+use crate::tool_profiles::{ToolFilter, tool_disabled};
 
 /// Remote tools that require API access
 #[derive(Clone)]
 pub struct RemoteTools {
     api_config: ClientConfig,
     secret_manager: SecretManager,
+    tool_filter: Arc<ToolFilter>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, JsonSchema)]
@@ -52,10 +57,15 @@ impl std::fmt::Display for Provisioner {
 
 #[tool(tool_box)]
 impl RemoteTools {
-    pub fn new(api_config: ClientConfig, redact_secrets: bool) -> Self {
+    pub fn new(
+        api_config: ClientConfig,
+        secret_manager: SecretManager,
+        tool_filter: Arc<ToolFilter>,
+    ) -> Self {
         Self {
             api_config,
-            secret_manager: SecretManager::new(redact_secrets),
+            secret_manager,
+            tool_filter,
         }
     }
 
@@ -717,19 +727,67 @@ impl RemoteTools {
     }
 }
 
-#[tool(tool_box)]
 impl ServerHandler for RemoteTools {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_prompts()
+                .enable_resources()
+                .enable_tools()
+                .build(),
             server_info: Implementation::from_build_env(),
             instructions: Some(
-                "This server provides remote tools for code generation and smart search using Stakpak API.".to_string(),
+                "This server provides remote tools for code generation and smart search using Stakpak API, the rulebooks and code index as resources, and a prompt to follow each rulebook.".to_string(),
             ),
         }
     }
 
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let mut listed: Vec<Resource> = resources::code_index_resource().into_iter().collect();
+        listed.extend(resources::rulebook_resources(&self.api_config).await);
+        Ok(ListResourcesResult::with_all_items(listed))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if request.uri == resources::CODE_INDEX_URI {
+            resources::read_code_index(&self.secret_manager)
+        } else if resources::is_rulebook_uri(&request.uri) {
+            resources::read_rulebook(&self.api_config, &request.uri, &self.secret_manager).await
+        } else {
+            Err(McpError::resource_not_found(
+                "Resource not found",
+                Some(json!({ "uri": request.uri })),
+            ))
+        }
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult::with_all_items(
+            resources::rulebook_prompts(&self.api_config).await?,
+        ))
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        resources::get_rulebook_prompt(&self.api_config, request, &self.secret_manager).await
+    }
+
     async fn initialize(
         &self,
         _request: InitializeRequestParam,
@@ -737,4 +795,31 @@ impl ServerHandler for RemoteTools {
     ) -> Result<InitializeResult, McpError> {
         Ok(self.get_info())
     }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(
+            Self::tool_box()
+                .list()
+                .into_iter()
+                .filter(|tool| self.tool_filter.allows(&tool.name))
+                .collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.tool_filter.allows(&request.name) {
+            return Ok(tool_disabled(&request.name));
+        }
+        Self::tool_box()
+            .call(ToolCallContext::new(self, request, context))
+            .await
+    }
 }
@@ -0,0 +1,255 @@
+use rmcp::{Error as McpError, model::*};
+use serde_json::json;
+use stakpak_api::{Client, ClientConfig, ListRuleBook};
+use stakpak_shared::edit_journal::{JOURNAL_FILE, LEGACY_JOURNAL_FILE};
+use stakpak_shared::local_store::LocalStore;
+use stakpak_shared::secrets::redaction_store::{LEGACY_SESSION_STORE_FILE, SESSION_STORE_FILE};
+use tracing::warn;
+
+use crate::secret_manager::SecretManager;
+
+/// Files of the session directory, like saved command outputs
+pub const SESSION_URI_PREFIX: &str = "stakpak://session/";
+/// The local code index built for the remote search tools
+pub const CODE_INDEX_URI: &str = "stakpak://code-index";
+const RULEBOOK_URI_SCHEME: &str = "stakpak://";
+
+const CODE_INDEX_FILE: &str = "code_index.json";
+/// Session files that aren't resources, the secret stores hold the values redaction hides and
+/// the edit journals hold whole file contents from before and after each edit
+const HIDDEN_SESSION_FILES: &[&str] = &[
+    SESSION_STORE_FILE,
+    LEGACY_SESSION_STORE_FILE,
+    JOURNAL_FILE,
+    LEGACY_JOURNAL_FILE,
+    CODE_INDEX_FILE,
+];
+
+const RULEBOOK_TASK_ARGUMENT: &str = "task";
+
+fn mime_type(file_name: &str) -> &'static str {
+    match file_name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("json") => "application/json",
+        Some("md") => "text/markdown",
+        _ => "text/plain",
+    }
+}
+
+fn text_contents(uri: &str, mime_type: &str, text: String) -> ReadResourceResult {
+    ReadResourceResult {
+        contents: vec![ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some(mime_type.to_string()),
+            text,
+        }],
+    }
+}
+
+fn resource(uri: String, name: String, description: String, mime_type: &str) -> Resource {
+    let mut resource = RawResource::new(uri, name);
+    resource.description = Some(description);
+    resource.mime_type = Some(mime_type.to_string());
+    resource.no_annotation()
+}
+
+/// Resources for the files of the session directory
+pub fn session_resources() -> Vec<Resource> {
+    let Ok(entries) = std::fs::read_dir(LocalStore::get_local_session_store_path()) else {
+        return Vec::new();
+    };
+    let mut file_names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|file_name| is_session_resource(file_name))
+        .collect();
+    file_names.sort();
+
+    file_names
+        .into_iter()
+        .map(|file_name| {
+            resource(
+                format!("{}{}", SESSION_URI_PREFIX, file_name),
+                file_name.clone(),
+                format!("Session file .stakpak/session/{}", file_name),
+                mime_type(&file_name),
+            )
+        })
+        .collect()
+}
+
+fn is_session_resource(file_name: &str) -> bool {
+    !file_name.is_empty()
+        && !file_name.starts_with('.')
+        && !file_name.contains(['/', '\\'])
+        && !HIDDEN_SESSION_FILES.contains(&file_name)
+}
+
+/// Read a session file, with its secrets redacted
+pub fn read_session_resource(
+    uri: &str,
+    secret_manager: &SecretManager,
+) -> Result<ReadResourceResult, McpError> {
+    let file_name = uri
+        .strip_prefix(SESSION_URI_PREFIX)
+        .filter(|file_name| is_session_resource(file_name))
+        .ok_or_else(|| {
+            McpError::resource_not_found("Resource not found", Some(json!({ "uri": uri })))
+        })?;
+    let content = LocalStore::read_session_data(file_name).map_err(|e| {
+        McpError::resource_not_found(
+            "Resource not found",
+            Some(json!({ "uri": uri, "error": e })),
+        )
+    })?;
+    let path = LocalStore::get_local_session_store_path().join(file_name);
+    let redacted = secret_manager.redact_and_store_secrets(&content, path.to_str());
+    Ok(text_contents(uri, mime_type(file_name), redacted))
+}
+
+/// The code index resource, if the project has been indexed
+pub fn code_index_resource() -> Option<Resource> {
+    LocalStore::get_local_session_store_path()
+        .join(CODE_INDEX_FILE)
+        .is_file()
+        .then(|| {
+            resource(
+                CODE_INDEX_URI.to_string(),
+                "Code index".to_string(),
+                "Blocks of the project's infrastructure code and their dependencies".to_string(),
+                "application/json",
+            )
+        })
+}
+
+/// Read the code index, with its secrets redacted
+pub fn read_code_index(secret_manager: &SecretManager) -> Result<ReadResourceResult, McpError> {
+    let content = LocalStore::read_session_data(CODE_INDEX_FILE).map_err(|e| {
+        McpError::resource_not_found(
+            "The project has not been indexed",
+            Some(json!({ "uri": CODE_INDEX_URI, "error": e })),
+        )
+    })?;
+    let redacted = secret_manager.redact_and_store_secrets(&content, None);
+    Ok(text_contents(CODE_INDEX_URI, "application/json", redacted))
+}
+
+async fn list_rulebooks(api_config: &ClientConfig) -> Result<Vec<ListRuleBook>, String> {
+    Client::new(api_config)?.list_rulebooks().await
+}
+
+/// Resources for the rulebooks of the account, listing fails quietly so the other resources
+/// stay browsable without API access
+pub async fn rulebook_resources(api_config: &ClientConfig) -> Vec<Resource> {
+    match list_rulebooks(api_config).await {
+        Ok(rulebooks) => rulebooks
+            .into_iter()
+            .map(|rulebook| {
+                let name = rulebook_name(&rulebook.uri).to_string();
+                resource(rulebook.uri, name, rulebook.description, "text/markdown")
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Failed to list rulebooks: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn is_rulebook_uri(uri: &str) -> bool {
+    uri.starts_with(RULEBOOK_URI_SCHEME)
+}
+
+/// Read a rulebook, with its secrets redacted. Only URIs the account's rulebooks are listed
+/// under are fetched, others could point the authenticated request elsewhere.
+pub async fn read_rulebook(
+    api_config: &ClientConfig,
+    uri: &str,
+    secret_manager: &SecretManager,
+) -> Result<ReadResourceResult, McpError> {
+    let content = get_rulebook_content(api_config, uri, secret_manager).await?;
+    Ok(text_contents(uri, "text/markdown", content))
+}
+
+async fn get_rulebook_content(
+    api_config: &ClientConfig,
+    uri: &str,
+    secret_manager: &SecretManager,
+) -> Result<String, McpError> {
+    let client = Client::new(api_config).map_err(|e| {
+        McpError::internal_error("Failed to create client", Some(json!({ "error": e })))
+    })?;
+    let rulebooks = client.list_rulebooks().await.map_err(|e| {
+        McpError::internal_error("Failed to list rulebooks", Some(json!({ "error": e })))
+    })?;
+    if !rulebooks.iter().any(|rulebook| rulebook.uri == uri) {
+        return Err(McpError::resource_not_found(
+            "Rulebook not found",
+            Some(json!({ "uri": uri })),
+        ));
+    }
+    let rulebook = client.get_rulebook_by_uri(uri).await.map_err(|e| {
+        McpError::resource_not_found(
+            "Rulebook not found",
+            Some(json!({ "uri": uri, "error": e })),
+        )
+    })?;
+    Ok(secret_manager.redact_and_store_secrets(&rulebook.content, None))
+}
+
+/// Rulebook URIs without the scheme, prompt names are shown to users as they are
+fn rulebook_name(uri: &str) -> &str {
+    uri.strip_prefix(RULEBOOK_URI_SCHEME).unwrap_or(uri)
+}
+
+/// Prompts that have the model follow a rulebook, one for each rulebook of the account
+pub async fn rulebook_prompts(api_config: &ClientConfig) -> Result<Vec<Prompt>, McpError> {
+    let rulebooks = list_rulebooks(api_config).await.map_err(|e| {
+        McpError::internal_error("Failed to list rulebooks", Some(json!({ "error": e })))
+    })?;
+    Ok(rulebooks
+        .into_iter()
+        .map(|rulebook| {
+            Prompt::new(
+                rulebook_name(&rulebook.uri),
+                Some(rulebook.description),
+                Some(vec![PromptArgument {
+                    name: RULEBOOK_TASK_ARGUMENT.to_string(),
+                    description: Some("What to do by following the rulebook".to_string()),
+                    required: Some(false),
+                }]),
+            )
+        })
+        .collect())
+}
+
+/// The prompt of the rulebook named `request.name`, with its secrets redacted
+pub async fn get_rulebook_prompt(
+    api_config: &ClientConfig,
+    request: GetPromptRequestParam,
+    secret_manager: &SecretManager,
+) -> Result<GetPromptResult, McpError> {
+    let uri = format!("{}{}", RULEBOOK_URI_SCHEME, request.name);
+    let content = get_rulebook_content(api_config, &uri, secret_manager).await?;
+    let task = request
+        .arguments
+        .as_ref()
+        .and_then(|arguments| arguments.get(RULEBOOK_TASK_ARGUMENT))
+        .and_then(|task| task.as_str())
+        .filter(|task| !task.trim().is_empty());
+
+    let instruction = match task {
+        Some(task) => format!("{}\n\nFollow this rulebook while doing it:", task),
+        None => "Follow this rulebook:".to_string(),
+    };
+    Ok(GetPromptResult {
+        description: Some(format!("Follow the rulebook {}", uri)),
+        messages: vec![PromptMessage::new_text(
+            PromptMessageRole::User,
+            format!(
+                "{}\n\n<rulebook uri=\"{}\">\n{}\n</rulebook>",
+                instruction, uri, content
+            ),
+        )],
+    })
+}
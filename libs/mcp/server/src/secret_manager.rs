@@ -1,75 +1,42 @@
-use serde_json;
-use stakpak_shared::local_store::LocalStore;
-use stakpak_shared::secrets::{redact_password, redact_secrets, restore_secrets};
+use stakpak_shared::secrets::encryption::EncryptionKey;
+use stakpak_shared::secrets::redaction_store::RedactionStore;
 use std::collections::HashMap;
-use tracing::{error, warn};
+use std::sync::Arc;
 
-/// Handles secret redaction and restoration across different tool types
+/// Handles secret redaction and restoration across different tool types.
+///
+/// Clones share the same redaction store, create one manager per server and hand clones
+/// to every tool instance so concurrent tool calls see the same redaction keys.
 #[derive(Clone)]
 pub struct SecretManager {
     redact_secrets: bool,
+    store: Arc<RedactionStore>,
 }
 
 impl SecretManager {
-    pub fn new(redact_secrets: bool) -> Self {
-        Self { redact_secrets }
+    pub fn new(redact_secrets: bool) -> Result<Self, String> {
+        Ok(Self {
+            redact_secrets,
+            store: Arc::new(RedactionStore::open_session(Self::load_encryption_key()?)),
+        })
     }
 
-    /// Load the redaction map from the session file
-    pub fn load_session_redaction_map(&self) -> HashMap<String, String> {
-        match LocalStore::read_session_data("secrets.json") {
-            Ok(content) => {
-                if content.trim().is_empty() {
-                    return HashMap::new();
-                }
-
-                match serde_json::from_str::<HashMap<String, String>>(&content) {
-                    Ok(map) => map,
-                    Err(e) => {
-                        error!("Failed to parse session redaction map JSON: {}", e);
-                        HashMap::new()
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Failed to read session redaction map file: {}", e);
-                HashMap::new()
-            }
-        }
-    }
-
-    /// Save the redaction map to the session file
-    pub fn save_session_redaction_map(&self, redaction_map: &HashMap<String, String>) {
-        match serde_json::to_string_pretty(redaction_map) {
-            Ok(json_content) => {
-                if let Err(e) = LocalStore::write_session_data("secrets.json", &json_content) {
-                    error!("Failed to save session redaction map: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("Failed to serialize session redaction map to JSON: {}", e);
-            }
-        }
+    /// Load the key the session store is encrypted with. Failing to is an error, a throwaway
+    /// key would replace the saved redactions with ones no other run can read.
+    fn load_encryption_key() -> Result<EncryptionKey, String> {
+        EncryptionKey::default_path()
+            .and_then(|path| EncryptionKey::load_or_create(&path))
+            .map_err(|e| format!("Failed to load session secrets encryption key: {}", e))
     }
 
-    /// Add new redactions to the session map
-    pub fn add_to_session_redaction_map(&self, new_redactions: &HashMap<String, String>) {
-        if new_redactions.is_empty() {
-            return;
-        }
-
-        let mut existing_map = self.load_session_redaction_map();
-        existing_map.extend(new_redactions.clone());
-        self.save_session_redaction_map(&existing_map);
+    /// All redaction keys recorded in this session and the secrets they replace
+    pub fn session_redaction_map(&self) -> HashMap<String, String> {
+        self.store.snapshot()
     }
 
     /// Restore secrets in a string using the session redaction map
     pub fn restore_secrets_in_string(&self, input: &str) -> String {
-        let redaction_map = self.load_session_redaction_map();
-        if redaction_map.is_empty() {
-            return input.to_string();
-        }
-        restore_secrets(input, &redaction_map)
+        self.store.restore(input)
     }
 
     /// Redact secrets and add to session map
@@ -78,14 +45,7 @@ impl SecretManager {
             return content.to_string();
         }
 
-        // TODO: this is not thread safe, we need to use a mutex or an actor to protect the redaction map
-        let existing_redaction_map = self.load_session_redaction_map();
-        let redaction_result = redact_secrets(content, path, &existing_redaction_map);
-
-        // Add new redactions to session map
-        self.add_to_session_redaction_map(&redaction_result.redaction_map);
-
-        redaction_result.redacted_string
+        self.store.redact(content, path)
     }
 
     pub fn redact_and_store_password(&self, content: &str, password: &str) -> String {
@@ -93,13 +53,6 @@ impl SecretManager {
             return content.to_string();
         }
 
-        // TODO: this is not thread safe, we need to use a mutex or an actor to protect the redaction map
-        let existing_redaction_map = self.load_session_redaction_map();
-        let redaction_result = redact_password(content, password, &existing_redaction_map);
-
-        // Add new redactions to session map
-        self.add_to_session_redaction_map(&redaction_result.redaction_map);
-
-        redaction_result.redacted_string
+        self.store.redact_password(content, password)
     }
 }
@@ -5,17 +5,58 @@ SECRET HANDLING:
 - Output containing secrets will be redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]
 - You can use these placeholders in subsequent commands - they will be automatically restored to actual values before execution
 - Example: If you see 'export API_KEY=[REDACTED_SECRET:api-key:abc123]', you can use '[REDACTED_SECRET:api-key:abc123]' in later commands
+- Placeholders can also be passed as env values, e.g. env {'API_KEY': '[REDACTED_SECRET:api-key:abc123]'}, which keeps the secret out of the command line
 
-If the command's output exceeds 300 lines the result will be truncated and the full output will be saved to a file in the current directory";
+The command runs with sh unless another shell is given (bash, zsh or pwsh). Its stdin is empty unless stdin is given, commands waiting for input don't hang.
 
-pub const VIEW_DESCRIPTION: &str = "View the contents of a file or list the contents of a directory. Can read entire files or specific line ranges.
+Set pty to run the command in a pseudo terminal, for programs that behave differently without a TTY (e.g. `terraform apply` prompts, `aws sso login`, `gcloud auth login`). stdin is then typed into the terminal, which stays open for input: a command still waiting for input runs until its timeout, so set one. The terminal merges stdout and stderr, all the output is returned as stdout without escape sequences.
+
+The result holds the command's stdout, its stderr (after a [stderr] line) and its exit code or the signal that killed it, as text, followed by a JSON object with the exit_code, signal, duration_ms, timed_out, cancelled, stdout_truncated and stderr_truncated fields.
+
+If stdout or stderr exceeds 300 lines only its last 300 lines are returned, and the full stream is saved with an output id (stdout_output_id, stderr_output_id) to read or search it with read_command_output
+
+If the command runs longer than its timeout, it will be killed along with any processes it started, and the partial output will be returned with a [TIMED OUT] marker. Avoid commands that never exit on their own (e.g. `kubectl logs -f`, `tail -f`) unless you set a timeout";
+
+pub const READ_COMMAND_OUTPUT_DESCRIPTION: &str = "Read the full stdout or stderr of a run_command call whose result was truncated, using the output id it returned. Read a range of lines with view_range, or only the lines matching a regular expression with grep (both can be combined). At most 300 lines are returned per call, with their line numbers.
+
+Saved outputs are removed after 7 days, and only the 50 most recent are kept. Secrets in the output are redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]";
+
+pub const START_BACKGROUND_DESCRIPTION: &str = "Start a long-running shell command in the background (e.g. dev servers, `docker compose up`, `kubectl port-forward`, `tail -f`) and return immediately with a process id. Use read_process_output to check its output, send_process_input to write to its stdin, and stop_process to terminate it.
+
+SECRET HANDLING:
+- You can use secret placeholders like [REDACTED_SECRET:rule-id:hash] in the command, they will be restored to actual values before execution
+- Output read from the process will have secrets redacted";
+
+pub const LIST_PROCESSES_DESCRIPTION: &str = "List the background processes started with start_background, with their status, uptime and number of output lines.";
+
+pub const READ_PROCESS_OUTPUT_DESCRIPTION: &str = "Read the combined stdout and stderr of a background process. Without an offset, the most recent lines are returned. Pass the returned next offset on the following call to only get new output.
+
+Only the last 1000 lines of each process are kept, older output is dropped. Secrets in the output will be redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]";
+
+pub const SEND_PROCESS_INPUT_DESCRIPTION: &str = "Write input to the stdin of a running background process, a newline is appended by default. Secret placeholders like [REDACTED_SECRET:rule-id:hash] will be restored to actual values before sending.";
+
+pub const STOP_PROCESS_DESCRIPTION: &str = "Stop a background process along with any processes it started. The process is sent SIGTERM and killed if it doesn't exit within 5 seconds. Stopped or exited processes are removed from the process list.";
+
+pub const VIEW_DESCRIPTION: &str = "View the contents of a file or the tree of a directory. Can read entire files or specific line ranges.
 
 SECRET HANDLING:
 - File contents containing secrets will be redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]
 - These placeholders represent actual secret values that are safely stored for later use
 - You can reference these placeholders when working with the file content
 
-A maximum of 300 lines will be shown at a time, the rest will be truncated.";
+A maximum of 300 lines will be shown at a time, the rest will be truncated. Large files are streamed, so use view_range to read any part of multi-GB files such as logs. Lines longer than 2000 characters are cut.
+
+Text in other encodings than UTF-8 (UTF-16 with or without a BOM, Windows-1252) is decoded and the encoding is named in the output. Binary files are not shown, only their type and size; use hex_range to view their bytes as a hexdump.
+
+Directories are shown as a tree with file sizes, 2 levels deep by default, skipping the entries ignored by .gitignore. At most 300 entries are listed, closest to the directory first; the number of entries that are not listed is shown for each directory.";
+
+pub const GLOB_DESCRIPTION: &str = "Find files by name with a glob pattern such as `**/*.tf` or `k8s/*/deployment.yaml`, matched against paths relative to the search path. Files ignored by .gitignore are skipped by default. Returns a JSON object with the matching file paths, sorted, and whether the list was truncated. Prefer it over running find or ls in a shell.";
+
+pub const GREP_DESCRIPTION: &str = "Search file contents with a regular expression, recursively under a directory or in a single file. Files ignored by .gitignore and binary files are skipped. Returns a JSON object with the matches (path, line number, line and the requested context lines before and after), the number of files searched and whether the results were truncated. Prefer it over running grep or rg in a shell.
+
+SECRET HANDLING:
+- Secrets in matched and context lines are redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]
+- Placeholders in the pattern are restored to the actual secret values before searching";
 
 pub const STR_REPLACE_DESCRIPTION: &str = "Replace a specific string in a file with new text. The old_str must match exactly including whitespace and indentation.
 
@@ -28,6 +69,17 @@ When replacing code, ensure the new text maintains proper syntax, indentation, a
 
 pub const CREATE_DESCRIPTION: &str = "Create a new file with the specified content. Will fail if file already exists. When creating code, ensure the new text has proper syntax, indentation, and follows the codebase style. Parent directories will be created automatically if they don't exist.";
 
+pub const APPLY_PATCH_DESCRIPTION: &str = "Apply several edits across one or more files at once, either as a unified diff in `patch` or as a list of str_replace style `edits`. Every hunk is validated before any file is written: if one fails, no file is changed and each failing hunk is reported with the same error codes as str_replace (NO_MATCH, MULTIPLE_MATCHES). Prefer it over repeated str_replace calls for changes spanning several places or files.
+
+Hunks are matched by their context and removed lines, not their line numbers, so include enough unchanged context to make each hunk unique. The line counts of each `@@ -start,count +start,count @@` header must match the hunk's lines, they tell where the hunk ends. New files use `--- /dev/null` in a diff, or an edit with an empty old_str. Deleting files is not supported.
+
+SECRET HANDLING:
+- You can use secret placeholders like [REDACTED_SECRET:rule-id:hash] in the patch and edits, they are restored to the actual secret values before applying them";
+
+pub const UNDO_LAST_EDIT_DESCRIPTION: &str = "Undo the most recent file edit made with str_replace, create or apply_patch (which records one edit per file) in this session, restoring the file's previous content (files created by create are deleted). Call it repeatedly to undo earlier edits. Fails without changing anything if the file was modified after the edit.";
+
+pub const LIST_EDITS_DESCRIPTION: &str = "List the file edits made with str_replace, create and apply_patch in this session, oldest first, with the checkpoints they were made after and whether they were undone.";
+
 pub const GENERATE_CODE_DESCRIPTION: &str = "Advanced Generate/Edit devops configurations and infrastructure as code with suggested file names using a given prompt. This code generation/editing only works for Terraform, Kubernetes, Dockerfile, and Github Actions. If save_files is true, the generated files will be saved to the filesystem. The printed shell output will redact any secrets, will be replaced with a placeholder [REDACTED_SECRET:rule-id:short-hash]
 
 IMPORTANT: When breaking down large projects into multiple generation steps, always include previously generated files in the 'context' parameter to maintain coherent references and consistent structure across all generated files.";
@@ -52,14 +104,68 @@ pub const GENERATE_PASSWORD_DESCRIPTION: &str = "Generate a secure password with
 // Parameter descriptions
 pub const COMMAND_PARAM_DESCRIPTION: &str = "The shell command to execute";
 pub const WORK_DIR_PARAM_DESCRIPTION: &str = "Optional working directory for command execution";
+pub const TIMEOUT_SECONDS_PARAM_DESCRIPTION: &str = "Optional maximum number of seconds the command is allowed to run before it is killed (default: the server's configured command timeout, if any)";
+
+pub const ENV_PARAM_DESCRIPTION: &str = "Optional environment variables to set for the command, as a map of names to values. Secret placeholders in the values are restored just before execution. Variables that make the shell run code, such as BASH_ENV or LD_PRELOAD, are rejected";
+
+pub const STDIN_PARAM_DESCRIPTION: &str = "Optional text written to the command's stdin, which is closed afterwards. Without it the command gets an empty stdin";
+
+pub const SHELL_PARAM_DESCRIPTION: &str =
+    "Optional shell to run the command with: sh (default), bash, zsh or pwsh";
+
+pub const PTY_PARAM_DESCRIPTION: &str = "Optional, run the command in a pseudo terminal (default: false). Use it for programs that need a TTY, like interactive prompts or login flows";
+
+pub const TERMINAL_SIZE_PARAM_DESCRIPTION: &str = "Optional size of the pseudo terminal as [columns, rows], only used with pty (default: the size of the user's terminal, or [120, 40])";
+
+pub const PROCESS_ID_PARAM_DESCRIPTION: &str =
+    "The id of the background process, as returned by start_background";
+pub const OUTPUT_OFFSET_PARAM_DESCRIPTION: &str = "Optional output line offset to start reading from, use the next offset returned by the previous read to only get new output (default: the most recent lines)";
+pub const OUTPUT_LIMIT_PARAM_DESCRIPTION: &str =
+    "The maximum number of output lines to return (default: 100, max: 300)";
+pub const OUTPUT_ID_PARAM_DESCRIPTION: &str =
+    "The output id returned by run_command when its stdout or stderr was truncated";
+pub const OUTPUT_RANGE_PARAM_DESCRIPTION: &str = "Optional line range to read [start_line, end_line]. Line numbers are 1-indexed. Use -1 for end_line to read to the end of the output (default: the whole output)";
+pub const OUTPUT_GREP_PARAM_DESCRIPTION: &str =
+    "Optional regular expression, only the lines matching it are returned";
+pub const PROCESS_INPUT_PARAM_DESCRIPTION: &str = "The input to write to the process stdin";
+pub const NO_NEWLINE_PARAM_DESCRIPTION: &str =
+    "Whether to send the input without appending a newline (default: false)";
 
 pub const PATH_PARAM_DESCRIPTION: &str = "The path to the file or directory to view";
+pub const HEX_RANGE_PARAM_DESCRIPTION: &str = "Optional byte range to show as a hexdump [start_byte, end_byte), 0-indexed, at most 4096 bytes. Only available for binary files, text files are shown as text.";
+pub const DEPTH_PARAM_DESCRIPTION: &str =
+    "Optional number of directory levels to show, at most 10 (default: 2)";
+pub const INCLUDE_HIDDEN_PARAM_DESCRIPTION: &str =
+    "Optional flag to show hidden entries, whose names start with a dot (default: true)";
+pub const RESPECT_GITIGNORE_PARAM_DESCRIPTION: &str = "Optional flag to skip the entries ignored by the directory's .gitignore, and .git (default: true)";
 pub const VIEW_RANGE_PARAM_DESCRIPTION: &str = "Optional line range to view [start_line, end_line]. Line numbers are 1-indexed. Use -1 for end_line to read to end of file.";
 
+pub const SEARCH_PATH_PARAM_DESCRIPTION: &str =
+    "Optional directory or file to search in (default: the current directory)";
+pub const GLOB_PATTERN_PARAM_DESCRIPTION: &str = "The glob pattern to match file paths against, `*` doesn't cross directories while `**` matches any number of them (e.g. `**/*.yaml`)";
+pub const GLOB_LIMIT_PARAM_DESCRIPTION: &str =
+    "Optional maximum number of files to return, at most 1000 (default: 200)";
+pub const INCLUDE_IGNORED_PARAM_DESCRIPTION: &str =
+    "Optional flag to also search files ignored by .gitignore (default: false)";
+pub const GREP_PATTERN_PARAM_DESCRIPTION: &str =
+    "The regular expression to search for, in Rust regex syntax";
+pub const CASE_INSENSITIVE_PARAM_DESCRIPTION: &str =
+    "Optional flag to match without regard to case (default: false)";
+pub const CONTEXT_LINES_PARAM_DESCRIPTION: &str =
+    "Optional number of lines to show before and after each match, at most 10 (default: 0)";
+pub const GREP_INCLUDE_PARAM_DESCRIPTION: &str =
+    "Optional glob pattern the searched file paths must match (e.g. `*.tf` or `charts/**`)";
+pub const FILE_TYPE_PARAM_DESCRIPTION: &str = "Optional type of files to search: terraform, yaml, json, toml, docker, shell, python, go, rust, js, ts, java, markdown, ini, xml or sql";
+pub const MAX_RESULTS_PARAM_DESCRIPTION: &str =
+    "Optional maximum number of matches to return, at most 500 (default: 100)";
+
 pub const FILE_PATH_PARAM_DESCRIPTION: &str = "The path to the file to modify";
 pub const OLD_STR_PARAM_DESCRIPTION: &str =
     "The exact text to replace (must match exactly, including whitespace and indentation)";
 pub const NEW_STR_PARAM_DESCRIPTION: &str = "The new text to insert in place of the old text. When replacing code, ensure the new text maintains proper syntax, indentation, and follows the codebase style.";
+pub const PATCH_PARAM_DESCRIPTION: &str = "A unified diff (as produced by `diff -u` or `git diff`) with `--- a/path` and `+++ b/path` headers for each file. Leave empty when using edits";
+pub const PATCH_EDITS_PARAM_DESCRIPTION: &str = "Edits to apply in order, edits of the same file see the result of the previous ones. Leave empty when using patch";
+pub const PATCH_OLD_STR_PARAM_DESCRIPTION: &str = "The exact text to replace, including whitespace and indentation. Empty to create a new file with new_str as its content";
 pub const REPLACE_ALL_PARAM_DESCRIPTION: &str =
     "Whether to replace all occurrences of the old text in the file (default: false)";
 
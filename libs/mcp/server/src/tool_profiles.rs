@@ -0,0 +1,13 @@
+use rmcp::model::{CallToolResult, Content};
+pub use stakpak_shared::tool_filter::{ToolFilter, ToolProfile};
+
+/// Result of calling a tool the filter leaves out, as if the tool didn't exist
+pub fn tool_disabled(tool_name: &str) -> CallToolResult {
+    CallToolResult::error(vec![
+        Content::text("TOOL_DISABLED"),
+        Content::text(format!(
+            "The tool '{}' is disabled on this server",
+            tool_name
+        )),
+    ])
+}
@@ -0,0 +1,168 @@
+use crate::local_store::LocalStore;
+use regex::Regex;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const FILE_PREFIX: &str = "command.output.";
+const FILE_SUFFIX: &str = ".txt";
+
+/// Saved outputs older than this are removed
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Most saved outputs kept, the oldest ones are removed first
+const MAX_FILES: usize = 50;
+
+fn file_name(id: &str) -> String {
+    format!("{}{}{}", FILE_PREFIX, id, FILE_SUFFIX)
+}
+
+/// Save the full output of a command, which must already be redacted, to the session store.
+/// Returns the id to read it back with and the path of the file. Old outputs are removed.
+pub fn save_output(content: &str) -> Result<(String, String), String> {
+    let id = Uuid::new_v4().simple().to_string()[..8].to_string();
+    let path = LocalStore::write_session_data(&file_name(&id), content)?;
+    prune_outputs(
+        &LocalStore::get_local_session_store_path(),
+        MAX_FILES,
+        MAX_AGE,
+    );
+    Ok((id, path))
+}
+
+/// The output saved as `id` by `save_output`
+pub fn load_output(id: &str) -> Result<String, String> {
+    // Ids are hex, anything else could point outside the session store
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid output id {}", id));
+    }
+    LocalStore::read_session_data(&file_name(id))
+        .map_err(|_| format!("No saved output with id {}, it may have been removed", id))
+}
+
+/// Remove the saved outputs in `dir` older than `max_age`, and the oldest ones beyond
+/// `max_files`. Returns the number of files removed.
+pub fn prune_outputs(dir: &Path, max_files: usize, max_age: Duration) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let now = SystemTime::now();
+    let mut outputs = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX)
+        })
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    // Newest first
+    outputs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let mut removed = 0;
+    for (index, (modified, path)) in outputs.iter().enumerate() {
+        let age = now.duration_since(*modified).unwrap_or_default();
+        if (index >= max_files || age > max_age) && std::fs::remove_file(path).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Lines selected from a saved output
+#[derive(Debug, PartialEq)]
+pub struct OutputLines {
+    /// 1-based line numbers and lines
+    pub lines: Vec<(usize, String)>,
+    pub total_lines: usize,
+    /// Lines in the range matching the filter, listed or not
+    pub matching_lines: usize,
+}
+
+/// The lines of `content` in the 1-based inclusive range `[first, last]` (to the end without
+/// `last`) that match `filter`, at most `max_lines` of them
+pub fn select_lines(
+    content: &str,
+    first: usize,
+    last: Option<usize>,
+    filter: Option<&Regex>,
+    max_lines: usize,
+) -> OutputLines {
+    let mut selected = OutputLines {
+        lines: Vec::new(),
+        total_lines: 0,
+        matching_lines: 0,
+    };
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+        selected.total_lines = number;
+        if number < first || last.is_some_and(|last| number > last) {
+            continue;
+        }
+        if filter.is_some_and(|filter| !filter.is_match(line)) {
+            continue;
+        }
+        selected.matching_lines += 1;
+        if selected.lines.len() < max_lines {
+            selected.lines.push((number, line.to_string()));
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_select_lines() {
+        let content = (1..=10)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let selected = select_lines(&content, 3, Some(5), None, 300);
+        assert_eq!(
+            selected.lines,
+            vec![
+                (3, "line 3".to_string()),
+                (4, "line 4".to_string()),
+                (5, "line 5".to_string())
+            ]
+        );
+        assert_eq!(selected.total_lines, 10);
+
+        let filter = Regex::new(r"line \d0?$").unwrap();
+        let selected = select_lines(&content, 1, None, Some(&filter), 2);
+        assert_eq!(selected.matching_lines, 10);
+        assert_eq!(
+            selected.lines,
+            vec![(1, "line 1".to_string()), (2, "line 2".to_string())]
+        );
+
+        let filter = Regex::new("1").unwrap();
+        let selected = select_lines(&content, 2, None, Some(&filter), 300);
+        assert_eq!(selected.lines, vec![(10, "line 10".to_string())]);
+    }
+
+    #[test]
+    fn test_prune_outputs() {
+        let dir = TempDir::new().unwrap();
+        for id in ["a1", "b2", "c3"] {
+            std::fs::write(dir.path().join(file_name(id)), id).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::fs::write(dir.path().join("edits.json"), "[]").unwrap();
+
+        assert_eq!(prune_outputs(dir.path(), 2, MAX_AGE), 1);
+        assert!(!dir.path().join(file_name("a1")).exists());
+        assert!(dir.path().join(file_name("c3")).exists());
+
+        assert_eq!(prune_outputs(dir.path(), MAX_FILES, Duration::ZERO), 2);
+        assert!(dir.path().join("edits.json").exists());
+        assert!(load_output("../edits").is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use crate::local_store::LocalStore;
+use crate::secrets::encryption::EncryptionKey;
+use crate::workspace::WorkspaceRoots;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Serializes journal updates from concurrent MCP sessions and the CLI in the same process
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// A file mutation made through the MCP file tools
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditRecord {
+    pub id: String,
+    /// The tool that made the edit, e.g. `str_replace`
+    pub tool: String,
+    pub path: PathBuf,
+    /// Content before the edit, `None` if the edit created the file
+    pub before: Option<String>,
+    pub after: String,
+    /// Id of the agent's tool call, set by the CLI once the tool call returns
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub reverted: bool,
+}
+
+impl EditRecord {
+    /// One line summary, never includes the file content
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} {} {}{}{}",
+            &self.id[..8.min(self.id.len())],
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.tool,
+            self.path.display(),
+            self.tool_call_id
+                .as_ref()
+                .map(|id| format!(" (tool call {})", id))
+                .unwrap_or_default(),
+            if self.reverted { " [reverted]" } else { "" }
+        )
+    }
+}
+
+/// Position of an agent checkpoint in the journal, edits recorded after it belong to later steps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointMarker {
+    pub checkpoint_id: String,
+    /// Number of edits recorded before the checkpoint
+    pub edit_index: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Encrypted journal inside the session directory
+pub const JOURNAL_FILE: &str = "edits.enc";
+/// Plaintext journal written by older versions
+pub const LEGACY_JOURNAL_FILE: &str = "edits.json";
+
+/// Journal of the file edits made in the agent session of the current directory, stored in
+/// .stakpak/session/edits.enc with the content before and after each edit so they can be undone.
+/// The content has secrets restored, so the file is encrypted with the session secrets key.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EditJournal {
+    #[serde(default)]
+    pub edits: Vec<EditRecord>,
+    #[serde(default)]
+    pub checkpoints: Vec<CheckpointMarker>,
+}
+
+impl EditJournal {
+    pub fn default_path() -> PathBuf {
+        LocalStore::get_local_session_store_path().join(JOURNAL_FILE)
+    }
+
+    pub fn load(path: &Path, key: &EncryptionKey) -> Result<Self, String> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read edit journal {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        };
+        let content = key
+            .decrypt(&content)
+            .map_err(|e| format!("Failed to read edit journal {}: {}", path.display(), e))?;
+        serde_json::from_slice(&content)
+            .map_err(|e| format!("Failed to parse edit journal {}: {}", path.display(), e))
+    }
+
+    /// Write the journal to a temporary file and rename it over `path`
+    pub fn save(&self, path: &Path, key: &EncryptionKey) -> Result<(), String> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let content = serde_json::to_vec(self)
+            .map_err(|e| format!("Failed to serialize edit journal: {}", e))?;
+        let content = key.encrypt(&content)?;
+
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(format!(".{}.tmp", Uuid::new_v4()));
+        let tmp_path = PathBuf::from(tmp_path);
+
+        fs::write(&tmp_path, content)
+            .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, path).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("Failed to replace edit journal {}: {}", path.display(), e)
+        })
+    }
+
+    /// Load the journal at `path`, apply `f` and save it, holding the journal lock throughout.
+    /// The journal is saved even if `f` fails, as it may have reverted some edits already.
+    pub fn update<T>(
+        path: &Path,
+        key: &EncryptionKey,
+        f: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut journal = Self::load(path, key)?;
+        let result = f(&mut journal);
+        journal.save(path, key)?;
+        result
+    }
+
+    /// Load the journal of the current session
+    pub fn load_session() -> Result<Self, String> {
+        Self::load(&Self::default_path(), &EncryptionKey::load_default()?)
+    }
+
+    /// Update the journal of the current session, see [`EditJournal::update`]
+    pub fn update_session<T>(f: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        Self::update(&Self::default_path(), &EncryptionKey::load_default()?, f)
+    }
+
+    pub fn record(
+        &mut self,
+        tool: &str,
+        path: &Path,
+        before: Option<String>,
+        after: String,
+    ) -> &EditRecord {
+        self.edits.push(EditRecord {
+            id: Uuid::new_v4().to_string(),
+            tool: tool.to_string(),
+            path: path.to_path_buf(),
+            before,
+            after,
+            tool_call_id: None,
+            timestamp: Utc::now(),
+            reverted: false,
+        });
+        &self.edits[self.edits.len() - 1]
+    }
+
+    /// Attribute the edits not linked to a tool call yet to `tool_call_id`
+    pub fn tag_tool_call(&mut self, tool_call_id: &str) -> usize {
+        let mut tagged = 0;
+        for edit in self
+            .edits
+            .iter_mut()
+            .rev()
+            .take_while(|edit| edit.tool_call_id.is_none())
+        {
+            edit.tool_call_id = Some(tool_call_id.to_string());
+            tagged += 1;
+        }
+        tagged
+    }
+
+    /// Record that the agent reached `checkpoint_id`, unless it's already the latest checkpoint
+    pub fn mark_checkpoint(&mut self, checkpoint_id: &str) {
+        if self
+            .checkpoints
+            .last()
+            .is_some_and(|marker| marker.checkpoint_id == checkpoint_id)
+        {
+            return;
+        }
+        self.checkpoints.push(CheckpointMarker {
+            checkpoint_id: checkpoint_id.to_string(),
+            edit_index: self.edits.len(),
+            timestamp: Utc::now(),
+        });
+    }
+
+    /// Revert the most recent edit that hasn't been reverted yet
+    pub fn undo_last(&mut self, workspace: &WorkspaceRoots) -> Result<EditRecord, String> {
+        let index = self
+            .edits
+            .iter()
+            .rposition(|edit| !edit.reverted)
+            .ok_or("There are no edits to undo")?;
+        self.revert(&[index], workspace)?;
+        Ok(self.edits[index].clone())
+    }
+
+    /// Revert every edit made after the agent reached `checkpoint_id`, most recent first
+    pub fn revert_to_checkpoint(
+        &mut self,
+        checkpoint_id: &str,
+        workspace: &WorkspaceRoots,
+    ) -> Result<Vec<EditRecord>, String> {
+        let marker = self
+            .checkpoints
+            .iter()
+            .find(|marker| marker.checkpoint_id == checkpoint_id)
+            .ok_or(format!(
+                "Checkpoint {} is not in the edit journal",
+                checkpoint_id
+            ))?;
+        let indices = (marker.edit_index..self.edits.len())
+            .filter(|&index| !self.edits[index].reverted)
+            .collect::<Vec<_>>();
+        self.revert(&indices, workspace)?;
+        Ok(indices
+            .into_iter()
+            .rev()
+            .map(|index| self.edits[index].clone())
+            .collect())
+    }
+
+    /// Restore the content from before the edits at `indices` (in the order they were made).
+    /// Nothing is reverted if a file is outside `workspace` or has changed since its last edit,
+    /// to not lose those changes.
+    fn revert(&mut self, indices: &[usize], workspace: &WorkspaceRoots) -> Result<(), String> {
+        let mut resolved_paths = HashMap::new();
+        for &index in indices {
+            let path = &self.edits[index].path;
+            let resolved = workspace
+                .resolve(&path.to_string_lossy())
+                .map_err(|e| format!("Not reverting, {}", e))?;
+            resolved_paths.insert(index, resolved);
+        }
+
+        let mut latest = HashMap::new();
+        for &index in indices {
+            latest.insert(&resolved_paths[&index], &self.edits[index].after);
+        }
+        let mut conflicts = latest
+            .into_iter()
+            .filter(|(path, after)| fs::read_to_string(path).ok().as_ref() != Some(*after))
+            .map(|(path, _)| path.display().to_string())
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            return Err(format!(
+                "Not reverting, these files were changed after the agent edited them: {}",
+                conflicts.join(", ")
+            ));
+        }
+
+        for &index in indices.iter().rev() {
+            let path = &resolved_paths[&index];
+            let edit = &mut self.edits[index];
+            match &edit.before {
+                Some(before) => fs::write(path, before),
+                None => fs::remove_file(path),
+            }
+            .map_err(|e| format!("Failed to revert {}: {}", edit.path.display(), e))?;
+            edit.reverted = true;
+        }
+        Ok(())
+    }
+
+    /// The edits with the checkpoints they were made after, oldest first
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        let mut checkpoints = self.checkpoints.iter().peekable();
+        for (index, edit) in self.edits.iter().enumerate() {
+            while let Some(marker) = checkpoints.next_if(|marker| marker.edit_index <= index) {
+                lines.push(format!("checkpoint {}", marker.checkpoint_id));
+            }
+            lines.push(format!("  {}", edit.describe()));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn edit(journal: &mut EditJournal, path: &Path, content: &str) {
+        let before = fs::read_to_string(path).ok();
+        fs::write(path, content).unwrap();
+        journal.record("str_replace", path, before, content.to_string());
+    }
+
+    fn workspace(dir: &TempDir) -> WorkspaceRoots {
+        WorkspaceRoots::new(dir.path(), &[]).0
+    }
+
+    #[test]
+    fn test_undo_last() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("main.tf");
+        let mut journal = EditJournal::default();
+
+        edit(&mut journal, &file, "v1");
+        edit(&mut journal, &file, "v2");
+
+        let undone = journal.undo_last(&workspace(&dir)).unwrap();
+        assert_eq!(undone.after, "v2");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "v1");
+
+        journal.undo_last(&workspace(&dir)).unwrap();
+        assert!(!file.exists());
+        assert!(journal.undo_last(&workspace(&dir)).is_err());
+    }
+
+    #[test]
+    fn test_revert_to_checkpoint() {
+        let dir = TempDir::new().unwrap();
+        let main = dir.path().join("main.tf");
+        let vars = dir.path().join("variables.tf");
+        fs::write(&vars, "original").unwrap();
+        let mut journal = EditJournal::default();
+
+        edit(&mut journal, &main, "a");
+        journal.mark_checkpoint("checkpoint-1");
+        edit(&mut journal, &main, "b");
+        edit(&mut journal, &vars, "changed");
+        journal.mark_checkpoint("checkpoint-2");
+        journal.mark_checkpoint("checkpoint-2");
+        edit(&mut journal, &main, "c");
+
+        assert_eq!(journal.checkpoints.len(), 2);
+
+        let reverted = journal
+            .revert_to_checkpoint("checkpoint-1", &workspace(&dir))
+            .unwrap();
+        assert_eq!(reverted.len(), 3);
+        assert_eq!(reverted[0].after, "c");
+        assert_eq!(fs::read_to_string(&main).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&vars).unwrap(), "original");
+
+        assert!(
+            journal
+                .revert_to_checkpoint("unknown", &workspace(&dir))
+                .is_err()
+        );
+        // Reverted edits are skipped by later reverts
+        assert!(
+            journal
+                .revert_to_checkpoint("checkpoint-2", &workspace(&dir))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_revert_refuses_to_overwrite_later_changes() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("main.tf");
+        let mut journal = EditJournal::default();
+
+        edit(&mut journal, &file, "agent");
+        fs::write(&file, "user").unwrap();
+
+        assert!(
+            journal
+                .undo_last(&workspace(&dir))
+                .unwrap_err()
+                .contains("main.tf")
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "user");
+        assert!(!journal.edits[0].reverted);
+    }
+
+    #[test]
+    fn test_tag_tool_call_and_persistence() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("main.tf");
+        let journal_path = dir.path().join(".stakpak/session").join(JOURNAL_FILE);
+        let key = EncryptionKey::generate().unwrap();
+
+        EditJournal::update(&journal_path, &key, |journal| {
+            edit(journal, &file, "a");
+            journal.tag_tool_call("call_1");
+            edit(journal, &file, "b");
+            edit(journal, &file, "c");
+            assert_eq!(journal.tag_tool_call("call_2"), 2);
+            Ok(())
+        })
+        .unwrap();
+
+        // The journal holds file content with secrets restored, it's only stored encrypted
+        let raw = fs::read(&journal_path).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("call_1"));
+        assert!(EditJournal::load(&journal_path, &EncryptionKey::generate().unwrap()).is_err());
+
+        let journal = EditJournal::load(&journal_path, &key).unwrap();
+        let tool_calls = journal
+            .edits
+            .iter()
+            .map(|edit| edit.tool_call_id.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tool_calls,
+            vec![Some("call_1"), Some("call_2"), Some("call_2")]
+        );
+        assert!(!journal.summary().contains("\"c\""));
+    }
+
+    #[test]
+    fn test_revert_only_inside_workspace() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let file = outside.path().join("main.tf");
+        let mut journal = EditJournal::default();
+
+        // An entry for a file outside the workspace, like one forged in the journal file
+        edit(&mut journal, &file, "content");
+
+        assert!(journal.undo_last(&workspace(&dir)).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "content");
+        assert!(!journal.edits[0].reverted);
+    }
+}
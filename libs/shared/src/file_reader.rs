@@ -0,0 +1,380 @@
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes read from the start of a file to detect its encoding or type
+const SAMPLE_SIZE: usize = 8192;
+
+/// Bytes read at a time when streaming a file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Characters of a single line kept, longer lines (e.g. minified files) are cut
+pub const MAX_LINE_CHARS: usize = 2000;
+
+/// Bytes shown at most by one hexdump
+pub const MAX_HEXDUMP_BYTES: u64 = 4096;
+
+/// What a file holds, detected from its first bytes
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileKind {
+    Text {
+        encoding: &'static Encoding,
+        /// The encoding was guessed because the content isn't valid UTF-8 and has no BOM
+        guessed: bool,
+    },
+    Binary {
+        description: &'static str,
+    },
+}
+
+impl FileKind {
+    /// Detect the encoding or binary type of the file at `path` from its first bytes
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+        File::open(path)?
+            .take(SAMPLE_SIZE as u64)
+            .read_to_end(&mut sample)?;
+        Ok(Self::detect(&sample))
+    }
+
+    pub fn detect(sample: &[u8]) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(sample) {
+            return FileKind::Text {
+                encoding,
+                guessed: false,
+            };
+        }
+        if let Some(description) = magic_description(sample) {
+            return FileKind::Binary { description };
+        }
+        if let Some(encoding) = utf16_without_bom(sample) {
+            return FileKind::Text {
+                encoding,
+                guessed: true,
+            };
+        }
+        if sample.contains(&0) || control_byte_ratio(sample) > 0.3 {
+            return FileKind::Binary {
+                description: "binary data",
+            };
+        }
+        match std::str::from_utf8(sample) {
+            Ok(_) => FileKind::Text {
+                encoding: UTF_8,
+                guessed: false,
+            },
+            // A multi-byte character cut at the end of the sample
+            Err(e) if e.error_len().is_none() => FileKind::Text {
+                encoding: UTF_8,
+                guessed: false,
+            },
+            // Legacy 8-bit text, most commonly Windows-1252 (a superset of Latin-1)
+            Err(_) => FileKind::Text {
+                encoding: WINDOWS_1252,
+                guessed: true,
+            },
+        }
+    }
+}
+
+fn magic_description(sample: &[u8]) -> Option<&'static str> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "PNG image"),
+        (b"\xff\xd8\xff", "JPEG image"),
+        (b"GIF87a", "GIF image"),
+        (b"GIF89a", "GIF image"),
+        (b"%PDF-", "PDF document"),
+        (b"PK\x03\x04", "ZIP archive (or jar, docx, xlsx)"),
+        (b"\x1f\x8b", "gzip compressed data"),
+        (b"BZh", "bzip2 compressed data"),
+        (b"\xfd7zXZ\x00", "xz compressed data"),
+        (b"\x28\xb5\x2f\xfd", "zstd compressed data"),
+        (b"7z\xbc\xaf\x27\x1c", "7-zip archive"),
+        (b"\x7fELF", "ELF executable"),
+        (b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+        (b"\xca\xfe\xba\xbe", "Mach-O universal binary or Java class"),
+        (b"MZ", "Windows executable"),
+        (b"\x00asm", "WebAssembly module"),
+        (b"SQLite format 3\x00", "SQLite database"),
+    ];
+    MAGIC
+        .iter()
+        .find(|(magic, _)| sample.starts_with(magic))
+        .map(|(_, description)| *description)
+        .or_else(|| (sample.len() > 262 && &sample[257..262] == b"ustar").then_some("tar archive"))
+}
+
+/// UTF-16 text without a BOM, recognized by the NUL high bytes of ASCII characters
+fn utf16_without_bom(sample: &[u8]) -> Option<&'static Encoding> {
+    if sample.len() < 4 {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_nuls = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+    if odd_nuls * 10 >= pairs * 7 && even_nuls * 10 <= pairs {
+        Some(UTF_16LE)
+    } else if even_nuls * 10 >= pairs * 7 && odd_nuls * 10 <= pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Share of control bytes other than whitespace, high in binary data
+fn control_byte_ratio(sample: &[u8]) -> f64 {
+    if sample.is_empty() {
+        return 0.0;
+    }
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+        .count();
+    control as f64 / sample.len() as f64
+}
+
+/// Lines read from a file by `read_lines`
+#[derive(Debug, Default, PartialEq)]
+pub struct LinesRead {
+    /// The 1-based line numbers and content of the lines read
+    pub lines: Vec<(usize, String)>,
+    /// Number of lines in the file, if it was read to the end
+    pub total_lines: Option<usize>,
+    /// Number of lines cut at `MAX_LINE_CHARS`
+    pub cut_lines: usize,
+}
+
+/// Stream the file at `path` decoded from `encoding`, keeping at most `max_lines` lines from
+/// the 1-based `first_line` on. Stops after the last kept line unless `count_all` is set, in
+/// which case the rest of the file is read to count its lines. Line endings follow
+/// `str::lines`: `\n` or `\r\n`, and no empty line after a final newline.
+pub fn read_lines(
+    path: &Path,
+    encoding: &'static Encoding,
+    first_line: usize,
+    max_lines: usize,
+    count_all: bool,
+) -> io::Result<LinesRead> {
+    let mut file = File::open(path)?;
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut decoded = String::with_capacity(CHUNK_SIZE * 2);
+    let mut line = String::new();
+    let mut line_chars = 0;
+    let mut line_number = 1;
+    let mut result = LinesRead::default();
+    let last_line = first_line.saturating_add(max_lines.saturating_sub(1));
+
+    loop {
+        let read = file.read(&mut chunk)?;
+        let last = read == 0;
+        decoded.clear();
+        decoded.reserve(
+            decoder
+                .max_utf8_buffer_length(read)
+                .unwrap_or(CHUNK_SIZE * 3),
+        );
+        let _ = decoder.decode_to_string(&chunk[..read], &mut decoded, last);
+
+        let keep = |line_number: usize| {
+            max_lines > 0 && line_number >= first_line && line_number <= last_line
+        };
+        for c in decoded.chars() {
+            if c == '\n' {
+                if keep(line_number) {
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                    result.lines.push((line_number, std::mem::take(&mut line)));
+                }
+                line.clear();
+                line_chars = 0;
+                line_number += 1;
+                if line_number > last_line && !count_all {
+                    return Ok(result);
+                }
+                continue;
+            }
+            if keep(line_number) {
+                if line_chars < MAX_LINE_CHARS {
+                    line.push(c);
+                } else if line_chars == MAX_LINE_CHARS {
+                    result.cut_lines += 1;
+                }
+            }
+            line_chars += 1;
+        }
+
+        if last {
+            // A last line without a newline
+            if line_chars > 0 {
+                if keep(line_number) {
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                    result.lines.push((line_number, line));
+                }
+                line_number += 1;
+            }
+            result.total_lines = Some(line_number - 1);
+            return Ok(result);
+        }
+    }
+}
+
+/// Hexdump of the bytes `start..end` of the file at `path`, at most `MAX_HEXDUMP_BYTES`,
+/// in the `hexdump -C` format
+pub fn hexdump(path: &Path, start: u64, end: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(end.saturating_sub(start).min(MAX_HEXDUMP_BYTES))
+        .read_to_end(&mut bytes)?;
+
+    let mut lines = Vec::new();
+    for (index, row) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, byte) in row.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        lines.push(format!(
+            "{:08x}  {:<49} |{}|",
+            start + index as u64 * 16,
+            hex,
+            ascii
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            FileKind::detect("apiVersion: v1\nkind: Pod – é\n".as_bytes()),
+            FileKind::Text {
+                encoding: UTF_8,
+                guessed: false
+            }
+        );
+        assert_eq!(
+            FileKind::detect(b"name = caf\xe9\n"),
+            FileKind::Text {
+                encoding: WINDOWS_1252,
+                guessed: true
+            }
+        );
+        assert_eq!(
+            FileKind::detect(b"\xff\xfeW\x00r\x00i\x00t\x00e\x00"),
+            FileKind::Text {
+                encoding: UTF_16LE,
+                guessed: false
+            }
+        );
+        assert_eq!(
+            FileKind::detect(b"W\x00r\x00i\x00t\x00e\x00-\x00H\x00o\x00s\x00t\x00"),
+            FileKind::Text {
+                encoding: UTF_16LE,
+                guessed: true
+            }
+        );
+        assert_eq!(
+            FileKind::detect(b"\x7fELF\x02\x01\x01\x00\x00\x00"),
+            FileKind::Binary {
+                description: "ELF executable"
+            }
+        );
+        assert_eq!(
+            FileKind::detect(b"\x01\x02\x03\x00\x10\x11"),
+            FileKind::Binary {
+                description: "binary data"
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        let content = (1..=10)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        fs::write(&path, &content).unwrap();
+
+        let read = read_lines(&path, UTF_8, 3, 2, false).unwrap();
+        assert_eq!(
+            read.lines,
+            vec![(3, "line 3".to_string()), (4, "line 4".to_string())]
+        );
+        assert_eq!(read.total_lines, None);
+
+        let read = read_lines(&path, UTF_8, 9, 5, true).unwrap();
+        assert_eq!(read.lines.len(), 2);
+        assert_eq!(read.total_lines, Some(content.lines().count()));
+
+        let read = read_lines(&path, UTF_8, 20, 5, true).unwrap();
+        assert!(read.lines.is_empty());
+        assert_eq!(read.total_lines, Some(10));
+    }
+
+    #[test]
+    fn test_read_lines_transcodes_and_cuts_long_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("script.ps1");
+        let mut utf16 = vec![0xff, 0xfe];
+        for unit in "Write-Host 'héllo'\n".encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        fs::write(&path, &utf16).unwrap();
+        let FileKind::Text { encoding, .. } = FileKind::of_file(&path).unwrap() else {
+            panic!("not detected as text");
+        };
+        let read = read_lines(&path, encoding, 1, 10, true).unwrap();
+        assert_eq!(read.lines, vec![(1, "Write-Host 'héllo'".to_string())]);
+        assert_eq!(read.total_lines, Some(1));
+
+        let long = path.with_extension("min.js");
+        fs::write(&long, "x".repeat(MAX_LINE_CHARS + 10)).unwrap();
+        let read = read_lines(&long, UTF_8, 1, 10, true).unwrap();
+        assert_eq!(read.lines[0].1.len(), MAX_LINE_CHARS);
+        assert_eq!(read.cut_lines, 1);
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.bin");
+        fs::write(&path, b"\x7fELF\x02\x01\x01\x00abcdefghijklmnop").unwrap();
+
+        let dump = hexdump(&path, 0, 20).unwrap();
+        assert_eq!(
+            dump,
+            "00000000  7f 45 4c 46 02 01 01 00  61 62 63 64 65 66 67 68  |.ELF....abcdefgh|\n00000010  69 6a 6b 6c                                       |ijkl|"
+        );
+    }
+}
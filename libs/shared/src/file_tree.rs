@@ -0,0 +1,301 @@
+use crate::utils::{is_ignored_path, read_gitignore_patterns};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which entries of a directory tree to list
+#[derive(Debug, Clone)]
+pub struct TreeOptions {
+    /// Levels to list, 1 only lists the directory's own entries
+    pub depth: usize,
+    pub include_hidden: bool,
+    /// Skip the entries matching the root's .gitignore, and .git
+    pub respect_gitignore: bool,
+    /// Most entries listed in the whole tree, levels closer to the root are listed first
+    pub max_entries: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TreeEntry {
+    pub name: String,
+    pub is_directory: bool,
+    /// Size of files
+    pub size: Option<u64>,
+    /// Target of symbolic links, which are never followed
+    pub link_target: Option<String>,
+    /// Listed entries of a directory
+    pub children: Vec<TreeEntry>,
+    /// Number of entries of a directory, listed or not. None if it can't be read
+    pub entry_count: Option<usize>,
+}
+
+struct Node {
+    entry: TreeEntry,
+    path: PathBuf,
+    depth: usize,
+    children: Vec<usize>,
+}
+
+/// Read the tree under the directory `root`, breadth first so that the entry limit cuts the
+/// deepest levels. `can_enter` decides which subdirectories are listed, the entries of the
+/// others are only counted.
+pub fn read_tree(
+    root: &Path,
+    options: &TreeOptions,
+    can_enter: impl Fn(&Path) -> bool,
+) -> std::io::Result<TreeEntry> {
+    let ignore_patterns = if options.respect_gitignore {
+        read_gitignore_patterns(&root.to_string_lossy())
+    } else {
+        Vec::new()
+    };
+
+    let mut nodes = vec![Node {
+        entry: TreeEntry {
+            name: root.display().to_string(),
+            is_directory: true,
+            ..Default::default()
+        },
+        path: root.to_path_buf(),
+        depth: 0,
+        children: Vec::new(),
+    }];
+    let mut remaining = options.max_entries;
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(index) = queue.pop_front() {
+        let entries = match list_dir(&nodes[index].path, root, options, &ignore_patterns) {
+            Ok(entries) => entries,
+            Err(e) if index == 0 => return Err(e),
+            Err(_) => continue,
+        };
+        nodes[index].entry.entry_count = Some(entries.len());
+        let depth = nodes[index].depth + 1;
+        if depth > options.depth || (index > 0 && !can_enter(&nodes[index].path)) {
+            continue;
+        }
+
+        let listed = entries.len().min(remaining);
+        remaining -= listed;
+        for entry in entries.into_iter().take(listed) {
+            let path = entry.path();
+            let file_type = entry.file_type().ok();
+            let is_directory = file_type.is_some_and(|file_type| file_type.is_dir());
+            let link_target = file_type
+                .filter(|file_type| file_type.is_symlink())
+                .and_then(|_| fs::read_link(&path).ok())
+                .map(|target| target.display().to_string());
+            let size = if is_directory || link_target.is_some() {
+                None
+            } else {
+                entry.metadata().ok().map(|metadata| metadata.len())
+            };
+
+            let child = nodes.len();
+            nodes.push(Node {
+                entry: TreeEntry {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    is_directory,
+                    size,
+                    link_target,
+                    ..Default::default()
+                },
+                path,
+                depth,
+                children: Vec::new(),
+            });
+            nodes[index].children.push(child);
+            if is_directory {
+                queue.push_back(child);
+            }
+        }
+    }
+
+    Ok(build_entry(&mut nodes, 0))
+}
+
+/// The entry of `index` with its children, taken out of the flat list of nodes
+fn build_entry(nodes: &mut [Node], index: usize) -> TreeEntry {
+    let mut entry = std::mem::take(&mut nodes[index].entry);
+    entry.children = std::mem::take(&mut nodes[index].children)
+        .into_iter()
+        .map(|child| build_entry(nodes, child))
+        .collect();
+    entry
+}
+
+/// Entries of `dir` passing the options' filters, directories first then by name
+fn list_dir(
+    dir: &Path,
+    root: &Path,
+    options: &TreeOptions,
+    ignore_patterns: &[String],
+) -> std::io::Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !options.include_hidden && name.starts_with('.') {
+                return false;
+            }
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            !is_ignored_path(&relative.to_string_lossy(), ignore_patterns)
+                && !is_ignored_path(&name, ignore_patterns)
+        })
+        .map(|entry| {
+            let is_directory = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            (!is_directory, entry.file_name(), entry)
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    Ok(entries.into_iter().map(|(_, _, entry)| entry).collect())
+}
+
+/// The tree as `tree` style lines, with file sizes and the number of entries that weren't
+/// listed
+pub fn render_tree(root: &TreeEntry) -> String {
+    let mut output = String::new();
+    render_children(root, "", &mut output);
+    output
+}
+
+fn render_children(entry: &TreeEntry, indent: &str, output: &mut String) {
+    let unlisted = entry
+        .entry_count
+        .unwrap_or(0)
+        .saturating_sub(entry.children.len());
+    for (i, child) in entry.children.iter().enumerate() {
+        let is_last = i == entry.children.len() - 1 && unlisted == 0;
+        let (prefix, child_indent) = if is_last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        output.push_str(&format!("{}{}{}\n", indent, prefix, describe(child)));
+        if !child.children.is_empty() {
+            render_children(child, &format!("{}{}", indent, child_indent), output);
+        }
+    }
+    match unlisted {
+        _ if entry.children.is_empty() => {}
+        0 => {}
+        1 => output.push_str(&format!("{}└── … 1 more entry\n", indent)),
+        _ => output.push_str(&format!("{}└── … {} more entries\n", indent, unlisted)),
+    }
+}
+
+/// The line of an entry, without its children
+fn describe(entry: &TreeEntry) -> String {
+    if let Some(target) = &entry.link_target {
+        return format!("{} -> {}", entry.name, target);
+    }
+    if !entry.is_directory {
+        return match entry.size {
+            Some(size) => format!("{} ({})", entry.name, format_file_size(size)),
+            None => entry.name.clone(),
+        };
+    }
+    match entry.entry_count {
+        _ if !entry.children.is_empty() => format!("{}/", entry.name),
+        Some(0) => format!("{}/ (empty)", entry.name),
+        Some(1) => format!("{}/ (1 entry)", entry.name),
+        Some(count) => format!("{}/ ({} entries)", entry.name, count),
+        None => format!("{}/ (unreadable)", entry.name),
+    }
+}
+
+pub fn format_file_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size_f = size as f64;
+    let mut unit_index = 0;
+
+    while size_f >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size_f /= 1024.0;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{} {}", size, UNITS[unit_index])
+    } else {
+        format!("{:.1} {}", size_f, UNITS[unit_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn options(depth: usize, max_entries: usize) -> TreeOptions {
+        TreeOptions {
+            depth,
+            include_hidden: false,
+            respect_gitignore: true,
+            max_entries,
+        }
+    }
+
+    fn create_tree() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join(".env"), "TOKEN=1\n").unwrap();
+        fs::write(root.join("build.log"), "log\n").unwrap();
+        fs::write(root.join("main.tf"), "terraform {}\n").unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("modules/vpc")).unwrap();
+        fs::create_dir_all(root.join("modules/db")).unwrap();
+        fs::write(root.join("modules/vpc/main.tf"), "").unwrap();
+        fs::write(root.join("modules/vpc/outputs.tf"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_tree_depth_and_filters() {
+        let dir = create_tree();
+
+        let tree = read_tree(dir.path(), &options(2, 100), |_| true).unwrap();
+        assert_eq!(
+            render_tree(&tree),
+            "├── modules/\n│   ├── db/ (empty)\n│   └── vpc/ (2 entries)\n└── main.tf (13 B)\n"
+        );
+
+        let mut all = options(1, 100);
+        all.include_hidden = true;
+        all.respect_gitignore = false;
+        let tree = read_tree(dir.path(), &all, |_| true).unwrap();
+        let names = tree
+            .children
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "modules",
+                "target",
+                ".env",
+                ".gitignore",
+                "build.log",
+                "main.tf"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_tree_entry_limit() {
+        let dir = create_tree();
+
+        // The root level is listed before the deeper ones
+        let tree = read_tree(dir.path(), &options(3, 3), |_| true).unwrap();
+        assert_eq!(
+            render_tree(&tree),
+            "├── modules/\n│   ├── db/ (empty)\n│   └── … 1 more entry\n└── main.tf (13 B)\n"
+        );
+
+        let tree = read_tree(dir.path(), &options(3, 100), |path| !path.ends_with("vpc")).unwrap();
+        assert_eq!(tree.children[0].children[1].entry_count, Some(2));
+        assert!(tree.children[0].children[1].children.is_empty());
+    }
+}
@@ -1,6 +1,16 @@
+pub mod command_output;
+pub mod edit_journal;
+pub mod file_reader;
+pub mod file_tree;
 pub mod file_watcher;
+pub mod guardrails;
 pub mod helper;
 pub mod local_store;
 pub mod models;
+pub mod patch;
+pub mod policy;
+pub mod search;
 pub mod secrets;
+pub mod tool_filter;
 pub mod utils;
+pub mod workspace;
@@ -308,6 +308,10 @@ pub struct ToolCallResult {
 pub struct ToolCallResultProgress {
     pub id: Uuid,
     pub message: String,
+    /// The message is a chunk of raw terminal output with its ANSI sequences, to be appended
+    /// as is rather than as a line
+    #[serde(default)]
+    pub raw: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
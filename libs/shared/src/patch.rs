@@ -0,0 +1,390 @@
+/// A replacement of `old_str` by `new_str`, from a str_replace style edit or a diff hunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub old_str: String,
+    pub new_str: String,
+    pub replace_all: bool,
+    /// 1-based line where a hunk without context or removed lines inserts its text
+    pub insert_line: Option<usize>,
+}
+
+/// The edits to make to one file, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePatch {
+    pub path: String,
+    /// The file doesn't exist yet and is created from the edits
+    pub create: bool,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Why an edit couldn't be applied, the codes match the str_replace errors
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    NoMatch,
+    MultipleMatches(usize),
+}
+
+impl EditError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EditError::NoMatch => "NO_MATCH",
+            EditError::MultipleMatches(_) => "MULTIPLE_MATCHES",
+        }
+    }
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::NoMatch => write!(f, "No match found for the text to replace"),
+            EditError::MultipleMatches(n) => write!(
+                f,
+                "Found {} matches for the text to replace, provide more context to make a unique match",
+                n
+            ),
+        }
+    }
+}
+
+/// Apply `edits` to `content` in order, each edit sees the result of the previous ones.
+/// Returns every edit that failed, by index, so they can all be reported at once.
+pub fn apply_edits(content: &str, edits: &[TextEdit]) -> Result<String, Vec<(usize, EditError)>> {
+    let mut content = content.to_string();
+    let mut failures = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
+        if edit.old_str.is_empty() {
+            match edit.insert_line {
+                Some(line) => content = insert_at_line(&content, line, &edit.new_str),
+                // Without a line an edit can only fill an empty (new) file
+                None if content.is_empty() => content = edit.new_str.clone(),
+                None => failures.push((index, EditError::NoMatch)),
+            }
+            continue;
+        }
+        match (content.matches(&edit.old_str).count(), edit.replace_all) {
+            (0, _) => failures.push((index, EditError::NoMatch)),
+            (1, _) | (_, true) => content = content.replace(&edit.old_str, &edit.new_str),
+            (n, false) => failures.push((index, EditError::MultipleMatches(n))),
+        }
+    }
+    if failures.is_empty() {
+        Ok(content)
+    } else {
+        Err(failures)
+    }
+}
+
+/// Insert `text` before the 1-based `line` of `content`, or at the end if it has fewer lines
+fn insert_at_line(content: &str, line: usize, text: &str) -> String {
+    let offset = content
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let mut result = content[..offset].to_string();
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(text);
+    result.push_str(&content[offset..]);
+    result
+}
+
+/// Parse a unified diff into the edits it makes to each file. Hunks are matched by their
+/// content rather than their line numbers, so diffs of files that changed since still apply.
+pub fn parse_unified_diff(diff: &str) -> Result<Vec<FilePatch>, String> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut lines = diff.lines().peekable();
+    let mut old_path: Option<String> = None;
+
+    while let Some(line) = lines.next() {
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_path = Some(diff_path(path));
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("+++ ") {
+            let new_path = diff_path(path);
+            let old_path = old_path
+                .take()
+                .ok_or(format!("'{}' is not preceded by a '---' line", line))?;
+            if new_path == "/dev/null" {
+                return Err(format!(
+                    "Deleting files is not supported, cannot delete {}",
+                    old_path
+                ));
+            }
+            patches.push(FilePatch {
+                path: new_path,
+                create: old_path == "/dev/null",
+                edits: Vec::new(),
+            });
+            continue;
+        }
+        let Some(header) = line.strip_prefix("@@") else {
+            // Lines between files, like `diff --git` or `index` lines
+            continue;
+        };
+        let patch = patches
+            .last_mut()
+            .ok_or(format!("Hunk '{}' comes before any file header", line))?;
+        let ranges = hunk_ranges(header).ok_or(format!("Invalid hunk header '{}'", line))?;
+        let body = hunk_body(&mut lines, ranges).ok_or(format!(
+            "The lines of hunk '{}' don't add up to its header",
+            line
+        ))?;
+        patch.edits.push(hunk_edit(&body, ranges.new_start));
+    }
+
+    if patches.is_empty() {
+        return Err(
+            "The patch doesn't contain any file headers ('--- a/path' and '+++ b/path')"
+                .to_string(),
+        );
+    }
+    Ok(patches)
+}
+
+/// The line ranges of a hunk header
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HunkRanges {
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+}
+
+/// The lines of a hunk, as many as its header counts. Counting is what tells a removed
+/// `-- comment` line (printed `--- comment`) from the header of the next file. None if the
+/// hunk ends before its counts are reached.
+fn hunk_body<'a>(
+    lines: &mut std::iter::Peekable<std::str::Lines<'a>>,
+    ranges: HunkRanges,
+) -> Option<Vec<&'a str>> {
+    let (mut old_left, mut new_left) = (ranges.old_len, ranges.new_len);
+    let mut body = Vec::new();
+    while old_left > 0 || new_left > 0 {
+        let line = lines.next()?;
+        match line.chars().next() {
+            Some('-') => old_left = old_left.checked_sub(1)?,
+            Some('+') => new_left = new_left.checked_sub(1)?,
+            Some('\\') => {}
+            // Blank lines are context lines that lost their space
+            Some(' ') | None => {
+                old_left = old_left.checked_sub(1)?;
+                new_left = new_left.checked_sub(1)?;
+            }
+            Some(_) => return None,
+        }
+        body.push(line);
+    }
+    // `\ No newline at end of file` after the last line
+    if let Some(line) = lines.next_if(|line| line.starts_with('\\')) {
+        body.push(line);
+    }
+    Some(body)
+}
+
+/// The replacement made by the lines of a hunk
+fn hunk_edit(body: &[&str], new_start: usize) -> TextEdit {
+    let (mut old_str, mut new_str) = (String::new(), String::new());
+    let mut previous = ' ';
+    for line in body {
+        let (kind, text) = match line.chars().next() {
+            Some(kind) => (kind, &line[kind.len_utf8()..]),
+            None => (' ', ""),
+        };
+        match kind {
+            '-' => old_str.push_str(&format!("{}\n", text)),
+            '+' => new_str.push_str(&format!("{}\n", text)),
+            // `\ No newline at end of file` applies to the line before it
+            '\\' => {
+                if previous != '+' {
+                    old_str.pop();
+                }
+                if previous != '-' {
+                    new_str.pop();
+                }
+            }
+            _ => {
+                old_str.push_str(&format!("{}\n", text));
+                new_str.push_str(&format!("{}\n", text));
+            }
+        }
+        previous = kind;
+    }
+    TextEdit {
+        old_str,
+        new_str,
+        replace_all: false,
+        insert_line: Some(new_start.max(1)),
+    }
+}
+
+/// The path of a `---` or `+++` line, without the `a/` or `b/` prefix and timestamps
+fn diff_path(path: &str) -> String {
+    let path = path.split('\t').next().unwrap_or(path).trim();
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// The ranges of a `@@ -1,3 +1,4 @@` hunk header, a range without a length has one line
+fn hunk_ranges(header: &str) -> Option<HunkRanges> {
+    let range = |prefix: char| -> Option<(usize, usize)> {
+        let range = header
+            .split_whitespace()
+            .find_map(|part| part.strip_prefix(prefix))?;
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (_, old_len) = range('-')?;
+    let (new_start, new_len) = range('+')?;
+    Some(HunkRanges {
+        old_len,
+        new_start,
+        new_len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(old_str: &str, new_str: &str) -> TextEdit {
+        TextEdit {
+            old_str: old_str.to_string(),
+            new_str: new_str.to_string(),
+            replace_all: false,
+            insert_line: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_edits_reports_every_failure() {
+        let content = "replicas: 1\nimage: nginx:1.25\nimage: nginx:1.25\n";
+
+        let applied = apply_edits(
+            content,
+            &[
+                edit("replicas: 1", "replicas: 3"),
+                edit("replicas: 3", "replicas: 5"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            applied,
+            "replicas: 5\nimage: nginx:1.25\nimage: nginx:1.25\n"
+        );
+
+        let failures = apply_edits(
+            content,
+            &[
+                edit("replicas: 2", "replicas: 3"),
+                edit("image: nginx:1.25", "image: nginx:1.27"),
+                edit("replicas: 1", "replicas: 3"),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            failures,
+            vec![(0, EditError::NoMatch), (1, EditError::MultipleMatches(2))]
+        );
+
+        let mut replace_all = edit("nginx:1.25", "nginx:1.27");
+        replace_all.replace_all = true;
+        assert_eq!(
+            apply_edits(content, &[replace_all]).unwrap(),
+            "replicas: 1\nimage: nginx:1.27\nimage: nginx:1.27\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_and_apply_unified_diff() {
+        let diff = "diff --git a/deploy.yaml b/deploy.yaml
+index 1234567..89abcde 100644
+--- a/deploy.yaml
++++ b/deploy.yaml
+@@ -1,4 +1,4 @@
+ kind: Deployment
+ spec:
+-  replicas: 1
++  replicas: 3
+   template:
+@@ -8,1 +8,2 @@ spec:
+       image: nginx
++      imagePullPolicy: Always
+--- /dev/null
++++ b/service.yaml
+@@ -0,0 +1,2 @@
++kind: Service
++name: web
+";
+        let patches = parse_unified_diff(diff).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].path, "deploy.yaml");
+        assert!(!patches[0].create);
+        assert_eq!(patches[0].edits.len(), 2);
+        assert_eq!(patches[1].path, "service.yaml");
+        assert!(patches[1].create);
+
+        let deployment = "kind: Deployment\nspec:\n  replicas: 1\n  template:\n    spec:\n      containers:\n      - name: web\n      image: nginx\n";
+        assert_eq!(
+            apply_edits(deployment, &patches[0].edits).unwrap(),
+            "kind: Deployment\nspec:\n  replicas: 3\n  template:\n    spec:\n      containers:\n      - name: web\n      image: nginx\n      imagePullPolicy: Always\n"
+        );
+        assert_eq!(
+            apply_edits("", &patches[1].edits).unwrap(),
+            "kind: Service\nname: web\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_unified_diff_without_trailing_newline() {
+        let diff = "--- a/VERSION
++++ b/VERSION
+@@ -1 +1 @@
+-1.0.0
+\\ No newline at end of file
++1.1.0
+\\ No newline at end of file
+";
+        let patches = parse_unified_diff(diff).unwrap();
+        assert_eq!(apply_edits("1.0.0", &patches[0].edits).unwrap(), "1.1.0");
+    }
+
+    #[test]
+    fn test_parse_unified_diff_lines_looking_like_headers() {
+        // Removing the SQL comment `-- old` and adding `++ new` print like file headers
+        let diff = "--- a/schema.sql
++++ b/schema.sql
+@@ -1,2 +1,2 @@
+--- old
++++ new
+ select 1;
+--- a/other.sql
++++ b/other.sql
+@@ -1 +1 @@
+-a
++b
+";
+        let patches = parse_unified_diff(diff).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].path, "schema.sql");
+        assert_eq!(
+            apply_edits("-- old\nselect 1;\n", &patches[0].edits).unwrap(),
+            "++ new\nselect 1;\n"
+        );
+        assert_eq!(patches[1].path, "other.sql");
+    }
+
+    #[test]
+    fn test_parse_unified_diff_errors() {
+        assert!(parse_unified_diff("just some text").is_err());
+        assert!(parse_unified_diff("--- a/main.tf\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n").is_err());
+        assert!(parse_unified_diff("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        // Fewer lines than the header counts
+        assert!(parse_unified_diff("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-a\n+b\n").is_err());
+    }
+}
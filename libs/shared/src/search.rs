@@ -0,0 +1,409 @@
+use crate::file_reader::{FileKind, read_lines};
+use crate::utils::{is_ignored_path, read_gitignore_patterns};
+use crate::workspace::WorkspaceRoots;
+use glob::{MatchOptions, Pattern};
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Files larger than this are skipped by grep
+const MAX_GREP_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Most context lines shown around a grep match
+pub const MAX_CONTEXT_LINES: usize = 10;
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// File types of the grep `file_type` filter and their extensions, capitalized entries are
+/// file name prefixes instead
+const FILE_TYPES: &[(&str, &[&str])] = &[
+    ("terraform", &["tf", "tfvars", "hcl"]),
+    ("yaml", &["yaml", "yml"]),
+    ("json", &["json"]),
+    ("toml", &["toml"]),
+    ("docker", &["Dockerfile", "dockerfile", "Containerfile"]),
+    ("shell", &["sh", "bash", "zsh"]),
+    ("python", &["py"]),
+    ("go", &["go"]),
+    ("rust", &["rs"]),
+    ("js", &["js", "mjs", "cjs", "jsx"]),
+    ("ts", &["ts", "tsx"]),
+    ("java", &["java"]),
+    ("markdown", &["md", "markdown"]),
+    ("ini", &["ini", "cfg", "conf"]),
+    ("xml", &["xml"]),
+    ("sql", &["sql"]),
+];
+
+/// Files under `root`, with the path to show for each. `base` is `root` as the user wrote it.
+/// Entries matching the root's .gitignore are skipped unless `include_ignored` is set, and
+/// directories and symlinks outside the workspace (like ~/.stakpak) are never entered.
+fn walk_files<'a>(
+    base: &'a str,
+    root: &'a Path,
+    workspace: &'a WorkspaceRoots,
+    include_ignored: bool,
+) -> impl Iterator<Item = (String, walkdir::DirEntry)> + 'a {
+    let ignore_patterns = if include_ignored {
+        vec![".git".to_string()]
+    } else {
+        read_gitignore_patterns(&root.to_string_lossy())
+    };
+    WalkDir::new(root)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |entry| {
+            if entry.depth() > 0 {
+                let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                let name = entry.file_name().to_string_lossy();
+                if is_ignored_path(&relative.to_string_lossy(), &ignore_patterns)
+                    || is_ignored_path(&name, &ignore_patterns)
+                {
+                    return false;
+                }
+            }
+            if entry.file_type().is_dir() || entry.path_is_symlink() {
+                return workspace.resolve(&entry.path().to_string_lossy()).is_ok();
+            }
+            true
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(move |entry| {
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let display = if relative.as_os_str().is_empty() {
+                base.to_string()
+            } else if base == "." || base == "./" {
+                relative.display().to_string()
+            } else {
+                Path::new(base).join(relative).display().to_string()
+            };
+            (display, entry)
+        })
+}
+
+/// Files found by `glob`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GlobResult {
+    pub files: Vec<String>,
+    /// More files matched than the limit
+    pub truncated: bool,
+}
+
+/// Files under `root` whose path relative to it matches `pattern` (`*`, `?`, `[...]`, and `**`
+/// for any number of directories), in path order
+pub fn glob(
+    base: &str,
+    root: &Path,
+    workspace: &WorkspaceRoots,
+    pattern: &str,
+    include_ignored: bool,
+    limit: usize,
+) -> Result<GlobResult, String> {
+    let pattern =
+        Pattern::new(pattern).map_err(|e| format!("Invalid glob pattern {}: {}", pattern, e))?;
+    let mut result = GlobResult {
+        files: Vec::new(),
+        truncated: false,
+    };
+    for (display, entry) in walk_files(base, root, workspace, include_ignored) {
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if !pattern.matches_path_with(relative, GLOB_OPTIONS) {
+            continue;
+        }
+        if result.files.len() == limit {
+            result.truncated = true;
+            break;
+        }
+        result.files.push(display);
+    }
+    Ok(result)
+}
+
+/// What `grep` looks for and where
+#[derive(Debug, Clone, Default)]
+pub struct GrepOptions {
+    /// Regular expression to search for
+    pub pattern: String,
+    pub case_insensitive: bool,
+    /// Lines of context before and after each match
+    pub context: usize,
+    /// Only search files matching this glob, against the file name if it has no `/`
+    pub include: Option<String>,
+    /// Only search files of this type, see `FILE_TYPES`
+    pub file_type: Option<String>,
+    pub include_ignored: bool,
+    pub max_results: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ContextLine {
+    pub line_number: usize,
+    pub line: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line_number: usize,
+    pub line: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<ContextLine>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<ContextLine>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    pub files_searched: usize,
+    /// Files skipped because they are binary or too large
+    pub files_skipped: usize,
+    /// More lines matched than `max_results`
+    pub truncated: bool,
+}
+
+/// Search the text files under `root` (or `root` itself if it's a file) for lines matching a
+/// regular expression. Files in other encodings than UTF-8 are decoded, binary files skipped.
+pub fn grep(
+    base: &str,
+    root: &Path,
+    workspace: &WorkspaceRoots,
+    options: &GrepOptions,
+) -> Result<GrepResult, String> {
+    let regex = RegexBuilder::new(&options.pattern)
+        .case_insensitive(options.case_insensitive)
+        .build()
+        .map_err(|e| format!("Invalid regular expression: {}", e))?;
+    let include = options
+        .include
+        .as_deref()
+        .map(|include| {
+            Pattern::new(include)
+                .map(|pattern| (pattern, include.contains('/')))
+                .map_err(|e| format!("Invalid include glob {}: {}", include, e))
+        })
+        .transpose()?;
+    let extensions = match options.file_type.as_deref() {
+        Some(file_type) => Some(
+            FILE_TYPES
+                .iter()
+                .find(|(name, _)| *name == file_type)
+                .map(|(_, extensions)| extensions.to_vec())
+                .ok_or(format!(
+                    "Unknown file type {}, use one of: {}",
+                    file_type,
+                    FILE_TYPES
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))?,
+        ),
+        None => None,
+    };
+    let context = options.context.min(MAX_CONTEXT_LINES);
+
+    let mut result = GrepResult {
+        matches: Vec::new(),
+        files_searched: 0,
+        files_skipped: 0,
+        truncated: false,
+    };
+    for (display, entry) in walk_files(base, root, workspace, options.include_ignored) {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let name = entry.file_name().to_string_lossy();
+        if let Some((pattern, match_path)) = &include {
+            let candidate = if *match_path {
+                relative
+            } else {
+                Path::new(name.as_ref())
+            };
+            if !pattern.matches_path_with(candidate, GLOB_OPTIONS) {
+                continue;
+            }
+        }
+        if let Some(extensions) = &extensions {
+            let matches_type = extensions.iter().any(|extension| {
+                if extension.starts_with(char::is_uppercase) {
+                    name.starts_with(extension)
+                } else {
+                    name.ends_with(&format!(".{}", extension))
+                }
+            });
+            if !matches_type {
+                continue;
+            }
+        }
+
+        let encoding = match (entry.metadata(), FileKind::of_file(path)) {
+            (Ok(metadata), Ok(FileKind::Text { encoding, .. }))
+                if metadata.len() <= MAX_GREP_FILE_BYTES =>
+            {
+                encoding
+            }
+            _ => {
+                result.files_skipped += 1;
+                continue;
+            }
+        };
+        let Ok(read) = read_lines(path, encoding, 1, usize::MAX, true) else {
+            result.files_skipped += 1;
+            continue;
+        };
+        result.files_searched += 1;
+
+        let lines = read.lines;
+        for (index, (line_number, line)) in lines.iter().enumerate() {
+            if !regex.is_match(line) {
+                continue;
+            }
+            if result.matches.len() == options.max_results {
+                result.truncated = true;
+                return Ok(result);
+            }
+            let context_line = |(line_number, line): &(usize, String)| ContextLine {
+                line_number: *line_number,
+                line: line.clone(),
+            };
+            result.matches.push(GrepMatch {
+                path: display.clone(),
+                line_number: *line_number,
+                line: line.clone(),
+                before: lines[index.saturating_sub(context)..index]
+                    .iter()
+                    .map(context_line)
+                    .collect(),
+                after: lines[index + 1..(index + 1 + context).min(lines.len())]
+                    .iter()
+                    .map(context_line)
+                    .collect(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, std::path::PathBuf, WorkspaceRoots) {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("k8s/base")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(
+            root.join("k8s/base/deploy.yaml"),
+            "kind: Deployment\nspec:\n  replicas: 1\n  image: nginx\n",
+        )
+        .unwrap();
+        fs::write(root.join("k8s/service.yml"), "kind: Service\n").unwrap();
+        fs::write(
+            root.join("main.tf"),
+            "resource \"aws_s3_bucket\" \"logs\" {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("build/deploy.yaml"), "kind: Deployment\n").unwrap();
+        fs::write(root.join("logo.png"), b"\x89PNG\r\n\x1a\nkind: Deployment").unwrap();
+        let (workspace, _) = WorkspaceRoots::new(&root, &[]);
+        (dir, root, workspace)
+    }
+
+    #[test]
+    fn test_glob() {
+        let (_dir, root, workspace) = workspace();
+
+        let result = glob(".", &root, &workspace, "**/*.yaml", false, 100).unwrap();
+        assert_eq!(result.files, vec!["k8s/base/deploy.yaml"]);
+
+        let result = glob(".", &root, &workspace, "**/*.y*ml", true, 100).unwrap();
+        assert_eq!(
+            result.files,
+            vec![
+                "build/deploy.yaml",
+                "k8s/base/deploy.yaml",
+                "k8s/service.yml"
+            ]
+        );
+
+        let result = glob("k8s", &root.join("k8s"), &workspace, "*", false, 100).unwrap();
+        assert_eq!(result.files, vec!["k8s/service.yml"]);
+
+        let result = glob(".", &root, &workspace, "**/*", false, 2).unwrap();
+        assert!(result.truncated);
+        assert!(glob(".", &root, &workspace, "[", false, 2).is_err());
+    }
+
+    #[test]
+    fn test_grep() {
+        let (_dir, root, workspace) = workspace();
+        let options = GrepOptions {
+            pattern: "^kind: (deployment|service)".to_string(),
+            case_insensitive: true,
+            max_results: 100,
+            ..Default::default()
+        };
+
+        let result = grep(".", &root, &workspace, &options).unwrap();
+        let paths = result
+            .matches
+            .iter()
+            .map(|m| m.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["k8s/base/deploy.yaml", "k8s/service.yml"]);
+        // The PNG is skipped as binary, build/ as ignored
+        assert_eq!(result.files_skipped, 1);
+
+        let result = grep(
+            ".",
+            &root,
+            &workspace,
+            &GrepOptions {
+                pattern: "replicas".to_string(),
+                context: 1,
+                file_type: Some("yaml".to_string()),
+                max_results: 100,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].line_number, 3);
+        assert_eq!(result.matches[0].before[0].line, "spec:");
+        assert_eq!(result.matches[0].after[0].line, "  image: nginx");
+
+        let result = grep(
+            ".",
+            &root,
+            &workspace,
+            &GrepOptions {
+                pattern: "kind".to_string(),
+                include: Some("*.yml".to_string()),
+                max_results: 100,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].path, "k8s/service.yml");
+
+        let mut limited = options.clone();
+        limited.max_results = 1;
+        assert!(grep(".", &root, &workspace, &limited).unwrap().truncated);
+
+        let mut invalid = options;
+        invalid.file_type = Some("cobol".to_string());
+        assert!(grep(".", &root, &workspace, &invalid).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Prefix of every encrypted file, bump the version if the format changes
+const MAGIC: &[u8] = b"STAKPAK-ENC-V1\n";
+const KEY_LEN: usize = 32;
+
+/// Key used to encrypt session secrets at rest, kept outside of the project directory
+#[derive(Clone)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    /// Location of the key file: ~/.stakpak/secrets.key
+    pub fn default_path() -> Result<PathBuf, String> {
+        let home_dir =
+            std::env::var("HOME").map_err(|_| "HOME environment variable not set".to_string())?;
+        Ok(PathBuf::from(home_dir).join(".stakpak").join("secrets.key"))
+    }
+
+    /// The key at the default path, created on first use
+    pub fn load_default() -> Result<Self, String> {
+        Self::default_path().and_then(|path| Self::load_or_create(&path))
+    }
+
+    pub fn generate() -> Result<Self, String> {
+        let mut key = [0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| "Failed to generate encryption key".to_string())?;
+        Ok(Self(key))
+    }
+
+    /// Read the key at `path`, creating it (readable by the current user only) if it doesn't exist
+    pub fn load_or_create(path: &Path) -> Result<Self, String> {
+        match Self::load(path) {
+            Ok(key) => return Ok(key),
+            Err(e) if path.exists() => return Err(e),
+            Err(_) => {}
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let key = Self::generate()?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(path) {
+            Ok(mut file) => {
+                file.write_all(&key.0)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                Ok(key)
+            }
+            // Another process created the key first, use theirs
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Self::load(path),
+            Err(e) => Err(format!("Failed to create {}: {}", path.display(), e)),
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let key = <[u8; KEY_LEN]>::try_from(bytes.as_slice())
+            .map_err(|_| format!("Invalid encryption key in {}", path.display()))?;
+        Ok(Self(key))
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey, String> {
+        UnboundKey::new(&AES_256_GCM, &self.0)
+            .map(LessSafeKey::new)
+            .map_err(|_| "Invalid encryption key".to_string())
+    }
+
+    /// Encrypt with AES-256-GCM, the output holds the format prefix, a random nonce and the ciphertext
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| "Failed to generate nonce".to_string())?;
+
+        let mut in_out = plaintext.to_vec();
+        self.aead_key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut in_out,
+            )
+            .map_err(|_| "Failed to encrypt data".to_string())?;
+
+        let mut output = Vec::with_capacity(MAGIC.len() + NONCE_LEN + in_out.len());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&in_out);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| "Data is not in the encrypted format".to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("Encrypted data is truncated".to_string());
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| "Encrypted data is truncated".to_string())?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .aead_key()?
+            .open_in_place(nonce, Aad::from(MAGIC), &mut in_out)
+            .map_err(|_| {
+                "Failed to decrypt data, it was encrypted with a different key or is corrupted"
+                    .to_string()
+            })?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let key = EncryptionKey::generate().unwrap();
+        let encrypted = key.encrypt(b"api_125136713").unwrap();
+
+        assert!(encrypted.starts_with(MAGIC));
+        assert!(!encrypted.windows(13).any(|w| w == b"api_125136713"));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), b"api_125136713");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_fails() {
+        let encrypted = EncryptionKey::generate()
+            .unwrap()
+            .encrypt(b"secret")
+            .unwrap();
+        let other_key = EncryptionKey::generate().unwrap();
+
+        assert!(other_key.decrypt(&encrypted).is_err());
+        assert!(other_key.decrypt(b"{\"plain\": \"json\"}").is_err());
+    }
+
+    #[test]
+    fn test_load_or_create_reuses_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("secrets.key");
+
+        let key = EncryptionKey::load_or_create(&path).unwrap();
+        let encrypted = key.encrypt(b"secret").unwrap();
+        let reloaded = EncryptionKey::load_or_create(&path).unwrap();
+
+        assert_eq!(reloaded.decrypt(&encrypted).unwrap(), b"secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -1,4 +1,7 @@
+pub mod encryption;
 pub mod gitleaks;
+pub mod redaction_store;
+pub mod scan;
 
 use gitleaks::{DetectedSecret, detect_secrets};
 use std::collections::HashMap;
@@ -0,0 +1,342 @@
+use super::encryption::EncryptionKey;
+use super::{redact_password, redact_secrets, restore_secrets};
+use crate::local_store::LocalStore;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tracing::error;
+
+/// Encrypted redaction map inside the session directory
+pub const SESSION_STORE_FILE: &str = "secrets.enc";
+/// Plaintext redaction map written by older versions
+pub const LEGACY_SESSION_STORE_FILE: &str = "secrets.json";
+
+/// In-process store of redaction keys and the secrets they replace, encrypted at rest.
+///
+/// All redactions go through a single mutex-guarded map so concurrent tool calls can't
+/// overwrite each other's entries, and the file on disk is replaced atomically.
+pub struct RedactionStore {
+    path: PathBuf,
+    key: EncryptionKey,
+    redaction_map: Mutex<HashMap<String, String>>,
+    /// False when the saved map couldn't be read, the store then only lives in memory so the
+    /// saved redactions aren't overwritten
+    persistent: bool,
+}
+
+impl RedactionStore {
+    /// Open the store backed by `path`, loading any redactions already saved there
+    pub fn open(path: impl Into<PathBuf>, key: EncryptionKey) -> Self {
+        let path = path.into();
+        let (redaction_map, persistent) = match Self::load(&path, &key) {
+            Ok(redaction_map) => (redaction_map, true),
+            Err(e) => {
+                error!("{}, new redactions won't be saved", e);
+                (HashMap::new(), false)
+            }
+        };
+
+        Self {
+            path,
+            key,
+            redaction_map: Mutex::new(redaction_map),
+            persistent,
+        }
+    }
+
+    /// Open the store of the current session, moving over the plaintext map of older versions
+    pub fn open_session(key: EncryptionKey) -> Self {
+        let session_dir = LocalStore::get_local_session_store_path();
+        let store = Self::open(session_dir.join(SESSION_STORE_FILE), key);
+        if let Err(e) = store.import_plaintext(&session_dir.join(LEGACY_SESSION_STORE_FILE)) {
+            error!("{}", e);
+        }
+        store
+    }
+
+    /// Delete the session store files, returns the paths that were removed
+    pub fn purge_session() -> Result<Vec<PathBuf>, String> {
+        let session_dir = LocalStore::get_local_session_store_path();
+        let mut removed = Vec::new();
+        for file in [SESSION_STORE_FILE, LEGACY_SESSION_STORE_FILE] {
+            let path = session_dir.join(file);
+            match fs::remove_file(&path) {
+                Ok(()) => removed.push(path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to remove {}: {}", path.display(), e)),
+            }
+        }
+        Ok(removed)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(path: &Path, key: &EncryptionKey) -> Result<HashMap<String, String>, String> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read redaction map from {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        };
+
+        if content.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let content = key.decrypt(&content).map_err(|e| {
+            format!(
+                "Failed to read redaction map from {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        serde_json::from_slice(&content).map_err(|e| {
+            format!(
+                "Failed to parse redaction map from {}: {}",
+                path.display(),
+                e
+            )
+        })
+    }
+
+    /// Merge a plaintext JSON redaction map into the store, then delete the plaintext file
+    /// unless the store only lives in memory
+    pub fn import_plaintext(&self, path: &Path) -> Result<(), String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        if !content.trim().is_empty() {
+            let imported: HashMap<String, String> = serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+            let mut redaction_map = self.lock();
+            for (redaction_key, secret) in imported {
+                redaction_map.entry(redaction_key).or_insert(secret);
+            }
+            self.persist(&redaction_map)?;
+        }
+        if !self.persistent {
+            return Ok(());
+        }
+
+        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.redaction_map
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write the map to a temporary file and rename it over the store file
+    fn persist(&self, redaction_map: &HashMap<String, String>) -> Result<(), String> {
+        if !self.persistent {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let content = serde_json::to_vec(redaction_map)
+            .map_err(|e| format!("Failed to serialize redaction map: {}", e))?;
+        let content = self.key.encrypt(&content)?;
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".{}.tmp", uuid::Uuid::new_v4()));
+        let tmp_path = PathBuf::from(tmp_path);
+
+        fs::write(&tmp_path, content)
+            .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("Failed to replace {}: {}", self.path.display(), e)
+        })
+    }
+
+    /// Merge new entries into the map and persist it, the caller must hold the lock
+    fn merge(
+        &self,
+        redaction_map: &mut HashMap<String, String>,
+        new_redactions: HashMap<String, String>,
+    ) {
+        let previous_len = redaction_map.len();
+        redaction_map.extend(new_redactions);
+        if redaction_map.len() == previous_len {
+            return;
+        }
+        if let Err(e) = self.persist(redaction_map) {
+            error!("Failed to save redaction map: {}", e);
+        }
+    }
+
+    /// A copy of all redaction keys and their secrets
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.lock().clone()
+    }
+
+    /// Redact secrets in `content` and record the new redaction keys
+    pub fn redact(&self, content: &str, path: Option<&str>) -> String {
+        // The lock is held during detection so the same secret always gets the same key
+        let mut redaction_map = self.lock();
+        let result = redact_secrets(content, path, &redaction_map);
+        self.merge(&mut redaction_map, result.redaction_map);
+        result.redacted_string
+    }
+
+    /// Redact a known password in `content` and record its redaction key
+    pub fn redact_password(&self, content: &str, password: &str) -> String {
+        let mut redaction_map = self.lock();
+        let result = redact_password(content, password, &redaction_map);
+        self.merge(&mut redaction_map, result.redaction_map);
+        result.redacted_string
+    }
+
+    /// Replace redaction keys in `input` with the secrets they stand for
+    pub fn restore(&self, input: &str) -> String {
+        let redaction_map = self.lock();
+        if redaction_map.is_empty() {
+            return input.to_string();
+        }
+        restore_secrets(input, &redaction_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_persists_and_reloads_redactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session").join("secrets.json");
+
+        let key = EncryptionKey::generate().unwrap();
+
+        let store = RedactionStore::open(&path, key.clone());
+        let redacted = store.redact_password("password is hunter2hunter2", "hunter2hunter2");
+        assert!(!redacted.contains("hunter2hunter2"));
+
+        // The secret must not be readable from the file on disk
+        let on_disk = fs::read(&path).unwrap();
+        assert!(!on_disk.windows(14).any(|w| w == b"hunter2hunter2"));
+
+        let reloaded = RedactionStore::open(&path, key);
+        assert_eq!(reloaded.snapshot(), store.snapshot());
+        assert_eq!(reloaded.restore(&redacted), "password is hunter2hunter2");
+    }
+
+    #[test]
+    fn test_same_secret_reuses_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedactionStore::open(
+            dir.path().join(SESSION_STORE_FILE),
+            EncryptionKey::generate().unwrap(),
+        );
+
+        let first = store.redact_password("one: s3cr3t-value", "s3cr3t-value");
+        let second = store.redact_password("two: s3cr3t-value", "s3cr3t-value");
+
+        assert_eq!(
+            first.trim_start_matches("one: "),
+            second.trim_start_matches("two: ")
+        );
+        assert_eq!(store.snapshot().len(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_redactions_are_not_lost() {
+        const THREADS: usize = 32;
+        const REDACTIONS_PER_THREAD: usize = 8;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_STORE_FILE);
+        let key = EncryptionKey::generate().unwrap();
+        let store = Arc::new(RedactionStore::open(&path, key.clone()));
+
+        let handles = (0..THREADS)
+            .map(|thread| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    (0..REDACTIONS_PER_THREAD)
+                        .map(|i| {
+                            let password = format!("pw-{}-{}-{}", thread, i, uuid::Uuid::new_v4());
+                            let content = format!("the password is {}", password);
+                            (content.clone(), store.redact_password(&content, &password))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let results = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+
+        // Every redaction must be restorable, both in memory and from the persisted file
+        let reloaded = RedactionStore::open(&path, key);
+        assert_eq!(reloaded.snapshot().len(), THREADS * REDACTIONS_PER_THREAD);
+        for (original, redacted) in &results {
+            assert_ne!(original, redacted);
+            assert_eq!(&store.restore(redacted), original);
+            assert_eq!(&reloaded.restore(redacted), original);
+        }
+
+        // No temporary files should be left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_imports_plaintext_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy_path = dir.path().join(LEGACY_SESSION_STORE_FILE);
+        fs::write(
+            &legacy_path,
+            r#"{"[REDACTED_SECRET:api-key:bga278]": "api_125136713"}"#,
+        )
+        .unwrap();
+
+        let path = dir.path().join(SESSION_STORE_FILE);
+        let key = EncryptionKey::generate().unwrap();
+        let store = RedactionStore::open(&path, key.clone());
+        store.import_plaintext(&legacy_path).unwrap();
+
+        assert!(!legacy_path.exists());
+        let reloaded = RedactionStore::open(&path, key);
+        assert_eq!(
+            reloaded.restore("echo [REDACTED_SECRET:api-key:bga278]"),
+            "echo api_125136713"
+        );
+    }
+
+    #[test]
+    fn test_wrong_key_starts_empty_without_overwriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_STORE_FILE);
+        let key = EncryptionKey::generate().unwrap();
+
+        let store = RedactionStore::open(&path, key.clone());
+        let redacted = store.redact_password("password is hunter2hunter2", "hunter2hunter2");
+
+        let reopened = RedactionStore::open(&path, EncryptionKey::generate().unwrap());
+        assert!(reopened.snapshot().is_empty());
+        reopened.redact_password("password is correcthorse", "correcthorse");
+
+        // The saved redactions are still there for the right key
+        let reloaded = RedactionStore::open(&path, key);
+        assert_eq!(reloaded.restore(&redacted), "password is hunter2hunter2");
+    }
+}
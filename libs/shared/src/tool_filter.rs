@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A named subset of the server's tools, from `[tool_profiles.<name>]` in the config
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ToolProfile {
+    /// Only these tools are served, all of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// Tools never served
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tools: Vec<String>,
+}
+
+/// Tools that only read files, command outputs and docs
+const READONLY_TOOLS: &[&str] = &[
+    "view",
+    "glob",
+    "grep",
+    "read_command_output",
+    "list_processes",
+    "read_process_output",
+    "list_edits",
+    "local_code_search",
+    "remote_code_search",
+    "search_docs",
+    "search_memory",
+    "read_rulebook",
+];
+
+/// Tools that run or drive processes
+const SHELL_TOOLS: &[&str] = &[
+    "run_command",
+    "start_background",
+    "send_process_input",
+    "stop_process",
+];
+
+/// Every tool of the agent's own server, in local, remote and combined mode
+const TOOL_NAMES: &[&str] = &[
+    "run_command",
+    "read_command_output",
+    "start_background",
+    "list_processes",
+    "read_process_output",
+    "send_process_input",
+    "stop_process",
+    "view",
+    "glob",
+    "grep",
+    "str_replace",
+    "create",
+    "apply_patch",
+    "undo_last_edit",
+    "list_edits",
+    "generate_password",
+    "generate_code",
+    "remote_code_search",
+    "local_code_search",
+    "search_docs",
+    "search_memory",
+    "read_rulebook",
+];
+
+/// Tools of configured MCP servers are named `<server>__<tool>`, they can't be known ahead
+const SERVER_TOOL_SEPARATOR: &str = "__";
+
+/// Fails on names that are neither one of the agent's tools nor a configured server's tool,
+/// a misspelled name would otherwise silently leave the agent without the tool
+fn validate_tool_names(names: &[String]) -> Result<(), String> {
+    match names
+        .iter()
+        .find(|name| !TOOL_NAMES.contains(&name.as_str()) && !name.contains(SERVER_TOOL_SEPARATOR))
+    {
+        Some(name) => Err(format!(
+            "Unknown tool '{}', available tools: {}",
+            name,
+            TOOL_NAMES.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+fn to_strings(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// The built-in profiles, a profile in the config with the same name replaces them
+pub fn builtin_profiles() -> HashMap<String, ToolProfile> {
+    HashMap::from([
+        (
+            "readonly".to_string(),
+            ToolProfile {
+                tools: to_strings(READONLY_TOOLS),
+                exclude_tools: Vec::new(),
+            },
+        ),
+        (
+            "no-shell".to_string(),
+            ToolProfile {
+                tools: Vec::new(),
+                exclude_tools: to_strings(SHELL_TOOLS),
+            },
+        ),
+    ])
+}
+
+/// Which tools a server lists and lets clients call, also applied to the tools of configured
+/// MCP servers by their `<server>__<tool>` names
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ToolFilter {
+    /// None allows every tool not excluded
+    allowed: Option<HashSet<String>>,
+    excluded: HashSet<String>,
+}
+
+impl ToolFilter {
+    /// The tools of `profile`, narrowed to `tools` when not empty and without `exclude_tools`.
+    /// Fails on unknown profiles and tool names.
+    pub fn new(
+        profile: Option<&str>,
+        profiles: &HashMap<String, ToolProfile>,
+        tools: &[String],
+        exclude_tools: &[String],
+    ) -> Result<Self, String> {
+        let mut filter = Self::default();
+        if let Some(name) = profile {
+            let mut all_profiles = builtin_profiles();
+            all_profiles.extend(profiles.clone());
+            let profile = all_profiles.get(name).ok_or_else(|| {
+                let mut names: Vec<_> = all_profiles.keys().cloned().collect();
+                names.sort();
+                format!(
+                    "Unknown tool profile '{}', available profiles: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+            validate_tool_names(&profile.tools)
+                .and_then(|_| validate_tool_names(&profile.exclude_tools))
+                .map_err(|e| format!("Tool profile '{}': {}", name, e))?;
+            filter.allow_only(&profile.tools);
+            filter.exclude(&profile.exclude_tools);
+        }
+        validate_tool_names(tools)?;
+        validate_tool_names(exclude_tools)?;
+        filter.allow_only(tools);
+        filter.exclude(exclude_tools);
+        Ok(filter)
+    }
+
+    fn allow_only(&mut self, tools: &[String]) {
+        if tools.is_empty() {
+            return;
+        }
+        let tools: HashSet<String> = tools.iter().cloned().collect();
+        self.allowed = Some(match self.allowed.take() {
+            Some(allowed) => allowed.intersection(&tools).cloned().collect(),
+            None => tools,
+        });
+    }
+
+    fn exclude(&mut self, tools: &[String]) {
+        self.excluded.extend(tools.iter().cloned());
+    }
+
+    pub fn allows(&self, tool_name: &str) -> bool {
+        !self.excluded.contains(tool_name)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(tool_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        to_strings(names)
+    }
+
+    #[test]
+    fn test_tools_narrow_and_exclude() {
+        let filter = ToolFilter::new(
+            None,
+            &HashMap::new(),
+            &names(&["view", "grep", "github__get_issue"]),
+            &names(&["grep"]),
+        )
+        .unwrap();
+        assert!(filter.allows("view"));
+        assert!(filter.allows("github__get_issue"));
+        assert!(!filter.allows("grep"));
+        assert!(!filter.allows("run_command"));
+        assert!(!filter.allows("github__create_issue"));
+
+        let all = ToolFilter::new(None, &HashMap::new(), &[], &[]).unwrap();
+        assert!(all.allows("run_command"));
+        assert!(all.allows("github__create_issue"));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let readonly = ToolFilter::new(Some("readonly"), &HashMap::new(), &[], &[]).unwrap();
+        assert!(readonly.allows("view"));
+        assert!(!readonly.allows("str_replace"));
+        assert!(!readonly.allows("github__create_issue"));
+
+        let no_shell = ToolFilter::new(Some("no-shell"), &HashMap::new(), &[], &[]).unwrap();
+        assert!(no_shell.allows("str_replace"));
+        assert!(!no_shell.allows("run_command"));
+
+        // Tools narrow the profile, they don't add to it
+        let narrowed = ToolFilter::new(
+            Some("readonly"),
+            &HashMap::new(),
+            &names(&["view", "run_command"]),
+            &[],
+        )
+        .unwrap();
+        assert!(narrowed.allows("view"));
+        assert!(!narrowed.allows("grep"));
+        assert!(!narrowed.allows("run_command"));
+
+        // A config profile replaces the built-in one of the same name
+        let profiles = HashMap::from([(
+            "readonly".to_string(),
+            ToolProfile {
+                tools: names(&["view"]),
+                exclude_tools: Vec::new(),
+            },
+        )]);
+        let replaced = ToolFilter::new(Some("readonly"), &profiles, &[], &[]).unwrap();
+        assert!(replaced.allows("view"));
+        assert!(!replaced.allows("grep"));
+    }
+
+    #[test]
+    fn test_unknown_names_are_rejected() {
+        let error = ToolFilter::new(None, &HashMap::new(), &names(&["veiw"]), &[]).unwrap_err();
+        assert!(error.contains("Unknown tool 'veiw'"));
+        assert!(ToolFilter::new(None, &HashMap::new(), &[], &names(&["run_comand"])).is_err());
+        assert!(ToolFilter::new(Some("nope"), &HashMap::new(), &[], &[]).is_err());
+
+        let profiles = HashMap::from([(
+            "mine".to_string(),
+            ToolProfile {
+                tools: names(&["veiw"]),
+                exclude_tools: Vec::new(),
+            },
+        )]);
+        let error = ToolFilter::new(Some("mine"), &profiles, &[], &[]).unwrap_err();
+        assert!(error.starts_with("Tool profile 'mine'"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use walkdir::DirEntry;
 
 /// Read .gitignore patterns from the specified base directory
@@ -31,13 +31,8 @@ pub fn should_include_entry(entry: &DirEntry, base_dir: &str, ignore_patterns: &
         Err(_) => path,
     };
 
-    let path_str = relative_path.to_string_lossy();
-
-    // Check if path matches any ignore pattern
-    for pattern in ignore_patterns {
-        if matches_gitignore_pattern(pattern, &path_str) {
-            return false;
-        }
+    if is_ignored_path(&relative_path.to_string_lossy(), ignore_patterns) {
+        return false;
     }
 
     // For files, also check if they are supported file types
@@ -48,6 +43,13 @@ pub fn should_include_entry(entry: &DirEntry, base_dir: &str, ignore_patterns: &
     }
 }
 
+/// Check if a path relative to the base directory matches any of the gitignore patterns
+pub fn is_ignored_path(relative_path: &str, ignore_patterns: &[String]) -> bool {
+    ignore_patterns
+        .iter()
+        .any(|pattern| matches_gitignore_pattern(pattern, relative_path))
+}
+
 /// Check if a path matches a gitignore pattern
 pub fn matches_gitignore_pattern(pattern: &str, path: &str) -> bool {
     // Basic gitignore pattern matching
@@ -121,6 +123,42 @@ pub fn is_supported_file(file_path: &Path) -> bool {
     }
 }
 
+/// Expand a leading `~` or `$HOME` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_default();
+    if path == "~" || path == "$HOME" {
+        return PathBuf::from(home);
+    }
+    match path
+        .strip_prefix("~/")
+        .or_else(|| path.strip_prefix("$HOME/"))
+    {
+        Some(rest) => PathBuf::from(home).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Make `path` absolute (relative to `base`) and resolve `.` and `..` without touching the
+/// filesystem, so paths that don't exist yet can be checked
+pub fn normalize_path(path: &Path, base: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,190 @@
+use crate::utils::{expand_home, normalize_path};
+use std::path::{Path, PathBuf};
+
+/// Directories the file tools may read and write. Paths are resolved through symlinks before
+/// they are checked, so links can't be used to escape the roots.
+#[derive(Debug, Clone)]
+pub struct WorkspaceRoots {
+    roots: Vec<PathBuf>,
+    denied: Vec<PathBuf>,
+}
+
+impl WorkspaceRoots {
+    /// The working directory plus `extra_roots` (`~` is the home directory). Extra roots that
+    /// don't exist are skipped and returned as errors. The ~/.stakpak directory, which holds the
+    /// API key and the secrets key, is never accessible.
+    pub fn new(workdir: &Path, extra_roots: &[String]) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut roots = Vec::new();
+
+        let workdir = normalize_path(workdir, Path::new("/"));
+        roots.push(workdir.canonicalize().unwrap_or_else(|_| workdir.clone()));
+        for root in extra_roots {
+            match normalize_path(&expand_home(root), &workdir).canonicalize() {
+                Ok(root) => roots.push(root),
+                Err(e) => errors.push(format!("Ignoring allowed path {}: {}", root, e)),
+            }
+        }
+
+        let stakpak_home = expand_home("~/.stakpak");
+        let denied = vec![
+            stakpak_home
+                .canonicalize()
+                .unwrap_or_else(|_| stakpak_home.clone()),
+        ];
+
+        (Self { roots, denied }, errors)
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolve `path` (relative paths are relative to the working directory) and check it's
+    /// inside one of the roots. Paths that don't exist yet, like files about to be created, are
+    /// resolved through their closest existing parent. Symlinks whose target doesn't exist are
+    /// rejected, writing through them would create the target wherever the link points. `..` is
+    /// applied before symlinks are followed, so a missing directory can't hide a link behind it.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let absolute = normalize_path(Path::new(path), &self.roots[0]);
+
+        let mut resolved = None;
+        for ancestor in absolute.ancestors() {
+            match ancestor.canonicalize() {
+                Ok(canonical) => {
+                    let rest = absolute.strip_prefix(ancestor).unwrap_or(Path::new(""));
+                    resolved = Some(normalize_path(rest, &canonical));
+                    break;
+                }
+                // The entry exists but can't be followed, like a dangling symlink
+                Err(e) if ancestor.symlink_metadata().is_ok() => {
+                    return Err(format!(
+                        "Cannot resolve path {}: {} can't be followed: {}",
+                        path,
+                        ancestor.display(),
+                        e
+                    ));
+                }
+                Err(_) => {}
+            }
+        }
+        let resolved = resolved.ok_or_else(|| format!("Cannot resolve path {}", path))?;
+
+        if self
+            .denied
+            .iter()
+            .any(|denied| resolved.starts_with(denied))
+        {
+            return Err(format!(
+                "Access to {} is not allowed, it holds Stakpak's credentials",
+                path
+            ));
+        }
+        if !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Err(format!(
+                "{} is outside the allowed directories: {}",
+                path,
+                self.roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, TempDir, WorkspaceRoots) {
+        let workdir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::create_dir_all(workdir.path().join("src")).unwrap();
+        fs::write(workdir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        let (roots, errors) = WorkspaceRoots::new(workdir.path(), &[]);
+        assert!(errors.is_empty());
+        (workdir, outside, roots)
+    }
+
+    #[test]
+    fn test_paths_inside_workdir() {
+        let (workdir, _outside, roots) = workspace();
+        let canonical = workdir.path().canonicalize().unwrap();
+
+        assert_eq!(
+            roots.resolve("src/main.rs"),
+            Ok(canonical.join("src/main.rs"))
+        );
+        assert_eq!(
+            roots.resolve(&canonical.join("src/../src/main.rs").to_string_lossy()),
+            Ok(canonical.join("src/main.rs"))
+        );
+        // Files that don't exist yet resolve through their closest existing parent
+        assert_eq!(
+            roots.resolve("new/dir/../file.txt"),
+            Ok(canonical.join("new/file.txt"))
+        );
+    }
+
+    #[test]
+    fn test_paths_outside_workdir() {
+        let (_workdir, outside, roots) = workspace();
+
+        assert!(
+            roots
+                .resolve(&outside.path().join("secret.txt").to_string_lossy())
+                .is_err()
+        );
+        assert!(roots.resolve("../../../../../../etc/passwd").is_err());
+        assert!(roots.resolve("new/../../escape.txt").is_err());
+        assert!(roots.resolve("/etc/passwd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_resolved() {
+        let (workdir, outside, roots) = workspace();
+        std::os::unix::fs::symlink(outside.path(), workdir.path().join("link")).unwrap();
+
+        assert!(roots.resolve("link/secret.txt").is_err());
+        assert!(roots.resolve("link/new.txt").is_err());
+        assert!(roots.resolve("missing/../link/secret.txt").is_err());
+        assert!(roots.resolve("missing/dir/../../link/new.txt").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dangling_symlinks_are_rejected() {
+        let (workdir, outside, roots) = workspace();
+        let target = outside.path().join("created.txt");
+        std::os::unix::fs::symlink(&target, workdir.path().join("dangling")).unwrap();
+        std::os::unix::fs::symlink("missing", workdir.path().join("dangling_inside")).unwrap();
+
+        assert!(roots.resolve("dangling").is_err());
+        assert!(roots.resolve("dangling/file.txt").is_err());
+        assert!(roots.resolve("dangling_inside").is_err());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_extra_roots() {
+        let workdir = TempDir::new().unwrap();
+        let extra = TempDir::new().unwrap();
+        let extra_path = extra.path().to_string_lossy().to_string();
+        let (roots, errors) = WorkspaceRoots::new(
+            workdir.path(),
+            &[extra_path.clone(), "/does/not/exist".to_string()],
+        );
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("/does/not/exist"));
+        assert_eq!(roots.roots().len(), 2);
+        assert!(roots.resolve(&format!("{}/file.txt", extra_path)).is_ok());
+    }
+}
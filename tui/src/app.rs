@@ -1,3 +1,4 @@
+use crate::services::diff::FileDiff;
 use crate::services::helper_block::push_styled_message;
 use crate::services::message::Message;
 use ratatui::style::{Color, Style};
@@ -22,6 +23,15 @@ pub struct SessionInfo {
     pub checkpoints: Vec<String>,
 }
 
+/// How broadly an "always allow" answer in the confirmation dialog applies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AllowScope {
+    /// Tool calls with the same command or path
+    ToolCall,
+    /// Every call of the tool
+    Tool,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum LoadingType {
     Llm,
@@ -44,6 +54,11 @@ pub struct AppState {
     pub is_dialog_open: bool,
     pub dialog_command: Option<ToolCall>,
     pub dialog_selected: usize,
+    /// Preview of the change when the dialog confirms a file edit
+    pub dialog_diff: Option<FileDiff>,
+    pub dialog_diff_scroll: usize,
+    /// Set when the user asked to edit the proposed content in their editor
+    pub dialog_edit_requested: bool,
     pub loading: bool,
     pub loading_type: LoadingType,
     pub spinner_frame: usize,
@@ -101,6 +116,8 @@ pub enum InputEvent {
     ToggleCursorVisible,
     Resized(u16, u16),
     ShowConfirmationDialog(ToolCall),
+    /// The diff of a file edit tool call, computed in the background
+    DialogDiffReady(ToolCall, Option<FileDiff>),
     DialogConfirm,
     DialogCancel,
     Tab,
@@ -121,10 +138,14 @@ pub enum InputEvent {
 pub enum OutputEvent {
     UserMessage(String, Option<Vec<ToolCallResult>>),
     AcceptTool(ToolCall),
+    /// Accept the tool call and add a rule allowing it to the approval policy
+    AlwaysAllowTool(ToolCall, AllowScope),
     RejectTool(ToolCall),
     ListSessions,
     SwitchToSession(String),
     Memorize,
+    /// Revert the most recent file edit made by the agent
+    UndoLastEdit,
     SendToolResult(ToolCallResult),
 }
 
@@ -187,6 +208,9 @@ impl AppState {
             is_dialog_open: false,
             dialog_command: None,
             dialog_selected: 0,
+            dialog_diff: None,
+            dialog_diff_scroll: 0,
+            dialog_edit_requested: false,
             loading: false,
             loading_type: LoadingType::Llm,
             spinner_frame: 0,
@@ -2,8 +2,9 @@ mod app;
 mod event;
 mod terminal;
 mod view;
-pub use app::{AppState, InputEvent, OutputEvent, SessionInfo};
+pub use app::{AllowScope, AppState, InputEvent, OutputEvent, SessionInfo};
 pub use ratatui::style::Color;
+pub use services::bash_block::pty_terminal_size;
 
 mod services;
 
@@ -11,6 +12,8 @@ use crossterm::{execute, terminal::EnterAlternateScreen};
 pub use event::map_crossterm_event_to_input_event;
 use ratatui::{Terminal, backend::CrosstermBackend};
 use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 pub use terminal::TerminalGuard;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::{Duration, interval};
@@ -27,14 +30,30 @@ pub async fn run_tui(
     execute!(std::io::stdout(), EnterAlternateScreen)?;
     let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
 
-    let all_helpers = vec!["/help", "/status", "/sessions", "/memorize", "/quit"];
+    let all_helpers = vec![
+        "/help",
+        "/status",
+        "/sessions",
+        "/memorize",
+        "/undo",
+        "/quit",
+    ];
     let mut state = AppState::new(all_helpers.clone(), latest_version);
 
     // Internal channel for event handling
     let (internal_tx, mut internal_rx) = tokio::sync::mpsc::channel::<InputEvent>(100);
     let internal_tx_thread = internal_tx.clone();
+    // Paused while an external editor owns the terminal, so it gets the keystrokes
+    let reader_paused = Arc::new(AtomicBool::new(false));
+    let reader_paused_thread = reader_paused.clone();
     std::thread::spawn(move || {
         loop {
+            if reader_paused_thread.load(Ordering::SeqCst)
+                || !crossterm::event::poll(std::time::Duration::from_millis(50)).unwrap_or(false)
+            {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                continue;
+            }
             if let Ok(event) = crossterm::event::read() {
                 if let Some(event) = crate::event::map_crossterm_event_to_input_event(event) {
                     if internal_tx_thread.blocking_send(event).is_err() {
@@ -149,6 +168,24 @@ pub async fn run_tui(
         if should_quit {
             break;
         }
+        if state.dialog_edit_requested {
+            state.dialog_edit_requested = false;
+            if let Some(content) = services::update::dialog_edit_content(&state) {
+                reader_paused.store(true, Ordering::SeqCst);
+                let edited = terminal::edit_in_external_editor(&content);
+                reader_paused.store(false, Ordering::SeqCst);
+                terminal.clear()?;
+                match edited {
+                    Ok(edited) if edited != content => {
+                        services::update::set_dialog_edit_content(&mut state, edited, &internal_tx)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        services::helper_block::push_error_message(&mut state, &e.to_string())
+                    }
+                }
+            }
+        }
         terminal.draw(|f| view::view(f, &state))?;
     }
 
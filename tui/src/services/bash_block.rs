@@ -23,8 +23,11 @@ fn preprocess_terminal_output(text: &str) -> String {
     let mut lines: Vec<String> = Vec::new();
     let mut current_line = String::new();
 
-    for ch in text.chars() {
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
         match ch {
+            // Terminals end lines with \r\n, which doesn't overwrite the line
+            '\r' if chars.peek() == Some(&'\n') => {}
             '\r' => {
                 // Carriage return - start overwriting the current line
                 current_line.clear();
@@ -170,6 +173,19 @@ fn wrap_ansi_text(text: &str, width: usize) -> Vec<String> {
     wrapped_lines
 }
 
+/// The `[columns, rows]` a PTY command gets so that its output fits in a block without
+/// wrapping: the terminal's width less the block's borders and indentation, and its height
+pub fn pty_terminal_size() -> Option<[u16; 2]> {
+    let (width, height) = crossterm::terminal::size().ok()?;
+    Some([width.saturating_sub(6), height])
+}
+
+/// The block's content color, overridden by the colors and modifiers of the content's own ANSI
+/// sequences
+fn content_style(content_color: Color, ansi_style: Style) -> Style {
+    Style::default().fg(content_color).patch(ansi_style)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn render_styled_block_ansi_to_tui(
     content: &str,
@@ -280,7 +296,7 @@ pub fn render_styled_block_ansi_to_tui(
             for s in &text_line.spans {
                 line_spans.push(Span::styled(
                     s.content.clone(),
-                    Style::default().fg(content_color),
+                    content_style(content_color, s.style),
                 ));
             }
             line_spans.push(Span::from(" ".repeat(right_pad)));
@@ -326,7 +342,7 @@ pub fn render_styled_block_ansi_to_tui(
                     for s in &first_line.spans {
                         line_spans.push(Span::styled(
                             s.content.clone(),
-                            Style::default().fg(content_color),
+                            content_style(content_color, s.style),
                         ));
                     }
                     line_spans.push(Span::from(" ".repeat(right_pad)));
@@ -367,11 +383,11 @@ pub fn extract_bash_block_info(
     tool_call: &ToolCall,
     output: &str,
 ) -> (String, String, String, BubbleColors) {
-    let full_command = extract_full_command_arguments(tool_call);
-    let command = if full_command == "unknown command" {
-        output.to_string()
+    // Callers pass what the block should show, like a file path or the formatted arguments
+    let command = if output.is_empty() {
+        extract_full_command_arguments(tool_call)
     } else {
-        full_command
+        output.to_string()
     };
     let outside_title = get_command_type_name(tool_call);
     let bubble_title = extract_command_purpose(&command, &outside_title);
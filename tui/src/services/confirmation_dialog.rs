@@ -13,8 +13,22 @@ pub fn render_confirmation_dialog(f: &mut Frame, state: &AppState) {
     let message_lines = get_wrapped_message_lines(&state.messages, screen.width as usize);
     let mut last_message_y = message_lines.len() as u16 + 1; // +1 for a gap
 
-    // Fixed dialog height: just 3 lines (border, message, border)
-    let dialog_height = 3;
+    let options = dialog_options(state);
+
+    // File edits show their diff above the options, in at most half of the screen
+    let diff_lines = state
+        .dialog_diff
+        .as_ref()
+        .map(|diff| diff.lines.as_slice())
+        .unwrap_or_default();
+    let diff_height = if diff_lines.is_empty() {
+        0
+    } else {
+        (diff_lines.len() as u16).min(screen.height / 2) + 1
+    };
+
+    // Borders, hint line, blank line, diff and one line per option
+    let dialog_height = options.len() as u16 + 4 + diff_height;
 
     // Clamp so dialog fits on screen
     if last_message_y + dialog_height > screen.height {
@@ -28,19 +42,78 @@ pub fn render_confirmation_dialog(f: &mut Frame, state: &AppState) {
         height: dialog_height,
     };
 
-    let line = Line::from(vec![Span::styled(
-        "Press Enter to continue, '$' to run the command yourself or Esc to cancel and reprompt",
-        Style::default()
-            .fg(Color::White)
-            .add_modifier(Modifier::BOLD),
-    )]);
-    let dialog = Paragraph::new(vec![line])
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::LightYellow))
-                .title("Confirmation"),
-        )
-        .alignment(Alignment::Center);
+    let hint = if diff_lines.is_empty() {
+        "Press Enter to continue, '$' to run the command yourself or Esc to cancel and reprompt"
+    } else {
+        "Press Enter to apply, 'e' to edit the new content, PgUp/PgDn to scroll or Esc to cancel and reprompt"
+    };
+    let mut lines = vec![
+        Line::from(vec![Span::styled(
+            hint,
+            Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )])
+        .alignment(Alignment::Center),
+        Line::from(""),
+    ];
+    if diff_height > 0 {
+        lines.extend(
+            diff_lines
+                .iter()
+                .skip(state.dialog_diff_scroll)
+                .take(diff_height as usize - 1)
+                .map(|line| line.to_line()),
+        );
+        lines.resize(diff_height as usize + 1, Line::from(""));
+        lines.push(Line::from(""));
+    }
+    for (i, option) in options.into_iter().enumerate() {
+        let line = if i == state.dialog_selected {
+            Line::from(vec![Span::styled(
+                format!("❯ {}", option),
+                Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+            )])
+        } else {
+            Line::from(vec![Span::styled(
+                format!("  {}", option),
+                Style::default().fg(Color::Gray),
+            )])
+        };
+        lines.push(line);
+    }
+
+    let dialog = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::LightYellow))
+            .title("Confirmation"),
+    );
     f.render_widget(dialog, area);
 }
+
+/// Labels of the dialog options, in the order of `dialog_selected`
+fn dialog_options(state: &AppState) -> Vec<String> {
+    let Some(tool_call) = &state.dialog_command else {
+        return vec!["Yes".to_string()];
+    };
+    let tool_name = &tool_call.function.name;
+    let arguments: serde_json::Value =
+        serde_json::from_str(&tool_call.function.arguments).unwrap_or_default();
+
+    let this_call = if arguments.get("command").is_some_and(|c| c.is_string()) {
+        "this command".to_string()
+    } else if let Some(path) = arguments.get("path").and_then(|p| p.as_str()) {
+        format!("{} on {}", tool_name, path)
+    } else {
+        tool_name.to_string()
+    };
+
+    vec![
+        "Yes".to_string(),
+        format!("Yes, and always allow {}", this_call),
+        format!("Yes, and always allow {}", tool_name),
+    ]
+}
@@ -0,0 +1,362 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use serde_json::Value;
+use stakpak_shared::models::integrations::openai::ToolCall;
+use stakpak_shared::secrets::redact_secrets;
+use stakpak_shared::workspace::WorkspaceRoots;
+use std::collections::HashMap;
+
+/// Lines of unchanged context around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Above this many line comparisons the changed region is shown as fully replaced
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Larger files aren't read, the diff only shows the replaced text
+const MAX_DIFF_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Tools whose calls are previewed as a diff
+const FILE_EDIT_TOOLS: &[&str] = &["create", "str_replace"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Header(String),
+    Hunk(String),
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+impl DiffLine {
+    pub fn to_line(&self) -> Line<'static> {
+        let (prefix, text, style) = match self {
+            DiffLine::Header(text) => (
+                "",
+                text,
+                Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            DiffLine::Hunk(text) => ("", text, Style::default().fg(Color::Cyan)),
+            DiffLine::Context(text) => (" ", text, Style::default().fg(Color::Gray)),
+            DiffLine::Added(text) => ("+", text, Style::default().fg(Color::Green)),
+            DiffLine::Removed(text) => ("-", text, Style::default().fg(Color::Red)),
+        };
+        Line::from(vec![Span::styled(format!("{}{}", prefix, text), style)])
+    }
+}
+
+/// Preview of the change a file editing tool call would make
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub path: String,
+    pub lines: Vec<DiffLine>,
+    /// The tool call argument holding the proposed content, which the user can edit
+    pub editable_argument: &'static str,
+}
+
+impl FileDiff {
+    /// The file `tool_call` edits, for file editing tools
+    pub fn edited_path(tool_call: &ToolCall) -> Option<String> {
+        if !FILE_EDIT_TOOLS.contains(&tool_call.function.name.as_str()) {
+            return None;
+        }
+        let arguments: Value = serde_json::from_str(&tool_call.function.arguments).ok()?;
+        Some(arguments.get("path")?.as_str()?.to_string())
+    }
+
+    /// Diff of the change `tool_call` would make to the file on disk, for file editing tools.
+    /// Reads and redacts the whole file, so it shouldn't run on the render loop.
+    pub fn from_tool_call(tool_call: &ToolCall) -> Option<Self> {
+        let arguments: Value = serde_json::from_str(&tool_call.function.arguments).ok()?;
+        let path = Self::edited_path(tool_call)?;
+        let argument = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let current = read_current(&path);
+        let (old, new, editable_argument) = match tool_call.function.name.as_str() {
+            "create" => (
+                current.unwrap_or_default(),
+                argument("file_text"),
+                "file_text",
+            ),
+            "str_replace" => {
+                let (old_str, new_str) = (argument("old_str"), argument("new_str"));
+                let replace_all = arguments
+                    .get("replace_all")
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or(false);
+                match current.filter(|current| !old_str.is_empty() && current.contains(&old_str)) {
+                    Some(current) => {
+                        let new = if replace_all {
+                            current.replace(&old_str, &new_str)
+                        } else {
+                            current.replacen(&old_str, &new_str, 1)
+                        };
+                        (current, new, "new_str")
+                    }
+                    // The text to replace may hold secret placeholders that only the MCP server
+                    // can restore, show the replaced text on its own
+                    None => (old_str, new_str, "new_str"),
+                }
+            }
+            _ => return None,
+        };
+
+        // Show secrets in the file the same way in both versions, as placeholders
+        let old = redact_secrets(&old, Some(&path), &HashMap::new());
+        let new = redact_secrets(&new, Some(&path), &old.redaction_map);
+
+        let mut lines = vec![
+            DiffLine::Header(format!("--- {}", path)),
+            DiffLine::Header(format!("+++ {}", path)),
+        ];
+        lines.extend(unified_diff(
+            &old.redacted_string,
+            &new.redacted_string,
+            CONTEXT_LINES,
+        ));
+        Some(Self {
+            path,
+            lines,
+            editable_argument,
+        })
+    }
+}
+
+/// The content of `path`, if it's a file in the working directory small enough to diff. The
+/// path comes from the agent, files outside the working directory are never read.
+fn read_current(path: &str) -> Option<String> {
+    let workdir = std::env::current_dir().ok()?;
+    let (workspace, _) = WorkspaceRoots::new(&workdir, &[]);
+    let resolved = workspace.resolve(path).ok()?;
+    let metadata = std::fs::metadata(&resolved).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_DIFF_FILE_BYTES {
+        return None;
+    }
+    std::fs::read_to_string(&resolved).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Line diff of `old` and `new` as unified diff hunks, with `context` unchanged lines around
+/// each change
+pub fn unified_diff(old: &str, new: &str, context: usize) -> Vec<DiffLine> {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let ops = diff_ops(&old_lines, &new_lines);
+
+    // Group the changes into hunks, merging changes closer than twice the context
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changes {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (start, end) in hunks {
+        let (mut old_start, mut new_start) = position(&ops[..start]);
+        let (old_count, new_count) = {
+            let (old_end, new_end) = position(&ops[..end]);
+            (old_end - old_start, new_end - new_start)
+        };
+        // Unified diffs number an empty range from the line before it
+        if old_count > 0 {
+            old_start += 1;
+        }
+        if new_count > 0 {
+            new_start += 1;
+        }
+        lines.push(DiffLine::Hunk(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start, old_count, new_start, new_count
+        )));
+        for op in &ops[start..end] {
+            lines.push(match *op {
+                Op::Equal(i, _) => DiffLine::Context(old_lines[i].to_string()),
+                Op::Delete(i) => DiffLine::Removed(old_lines[i].to_string()),
+                Op::Insert(j) => DiffLine::Added(new_lines[j].to_string()),
+            });
+        }
+    }
+    lines
+}
+
+/// Number of old and new lines covered by `ops`
+fn position(ops: &[Op]) -> (usize, usize) {
+    ops.iter().fold((0, 0), |(old, new), op| match op {
+        Op::Equal(..) => (old + 1, new + 1),
+        Op::Delete(_) => (old + 1, new),
+        Op::Insert(_) => (old, new + 1),
+    })
+}
+
+/// Longest common subsequence line diff. The common prefix and suffix are skipped first, so
+/// localized edits of large files stay cheap.
+fn diff_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops = (0..prefix).map(|i| Op::Equal(i, i)).collect::<Vec<_>>();
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n * m > MAX_DIFF_CELLS {
+        ops.extend((0..n).map(|i| Op::Delete(prefix + i)));
+        ops.extend((0..m).map(|j| Op::Insert(prefix + j)));
+    } else {
+        // lcs[i][j] is the length of the LCS of old_middle[i..] and new_middle[j..]
+        let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_middle[i] == new_middle[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                ops.push(Op::Equal(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lcs[i][j + 1] > lcs[i + 1][j]) {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            } else {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            }
+        }
+    }
+
+    let (old_offset, new_offset) = (old.len() - suffix, new.len() - suffix);
+    ops.extend((0..suffix).map(|k| Op::Equal(old_offset + k, new_offset + k)));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(lines: &[DiffLine]) -> String {
+        lines
+            .iter()
+            .map(|line| match line {
+                DiffLine::Header(text) | DiffLine::Hunk(text) => text.clone(),
+                DiffLine::Context(text) => format!(" {}", text),
+                DiffLine::Added(text) => format!("+{}", text),
+                DiffLine::Removed(text) => format!("-{}", text),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_unified_diff_single_change() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\n";
+
+        assert_eq!(
+            render(&unified_diff(old, new, 2)),
+            "@@ -3,5 +3,5 @@\n c\n d\n-e\n+E\n f\n g"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_separate_hunks() {
+        let old = (1..=20)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let new = old
+            .replacen("\n2\n", "\ntwo\n", 1)
+            .replace("19", "nineteen");
+
+        let diff = render(&unified_diff(&old, &new, 1));
+        assert_eq!(
+            diff,
+            "@@ -1,3 +1,3 @@\n 1\n-2\n+two\n 3\n@@ -18,3 +18,3 @@\n 18\n-19\n+nineteen\n 20"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_new_file() {
+        assert_eq!(
+            render(&unified_diff("", "one\ntwo", 3)),
+            "@@ -0,0 +1,2 @@\n+one\n+two"
+        );
+        assert!(unified_diff("same\n", "same\n", 3).is_empty());
+    }
+
+    #[test]
+    fn test_unified_diff_insertions_and_deletions() {
+        let old = "keep\nremove me\nkeep too\n";
+        let new = "keep\nkeep too\nadded\n";
+
+        assert_eq!(
+            render(&unified_diff(old, new, 3)),
+            "@@ -1,3 +1,3 @@\n keep\n-remove me\n keep too\n+added"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_files_outside_workdir_are_not_read() {
+        use stakpak_shared::models::integrations::openai::FunctionCall;
+
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "str_replace".to_string(),
+                arguments: serde_json::json!({
+                    "path": "/etc/passwd",
+                    "old_str": "root",
+                    "new_str": "admin",
+                })
+                .to_string(),
+            },
+        };
+
+        let diff = FileDiff::from_tool_call(&tool_call).unwrap();
+        assert_eq!(
+            render(&diff.lines),
+            "--- /etc/passwd\n+++ /etc/passwd\n@@ -1,1 +1,1 @@\n-root\n+admin"
+        );
+    }
+}
@@ -158,6 +158,7 @@ pub fn push_help_message(state: &mut AppState) {
         ("/status", "show account status"),
         ("/sessions", "show list of sessions"),
         ("/memorize", "memorize the conversation history"),
+        ("/undo", "undo the last file edit made by the agent"),
         ("/quit", "quit the app"),
     ];
     for (cmd, desc) in commands {
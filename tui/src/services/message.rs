@@ -458,6 +458,56 @@ pub fn extract_full_command_arguments(tool_call: &ToolCall) -> String {
     format!("function_name={}", tool_call.function.name)
 }
 
+/// The arguments of a run_command call for its confirmation, one per line in the order they
+/// matter to the user: the command, how it runs, then its environment and stdin
+pub fn format_run_command_arguments(tool_call: &ToolCall) -> Option<String> {
+    const MAX_STDIN_LINES: usize = 20;
+
+    if tool_call.function.name != "run_command" {
+        return None;
+    }
+    let Ok(Value::Object(args)) = serde_json::from_str::<Value>(&tool_call.function.arguments)
+    else {
+        return None;
+    };
+    let command = args.get("command")?.as_str()?;
+
+    let mut lines = vec![format!("command = {}", command)];
+    for key in ["shell", "work_dir", "timeout_seconds"] {
+        if let Some(value) = args.get(key).filter(|value| !value.is_null()) {
+            lines.push(format!("{} = {}", key, format_simple_value(value)));
+        }
+    }
+    if let Some(env) = args
+        .get("env")
+        .and_then(Value::as_object)
+        .filter(|env| !env.is_empty())
+    {
+        let vars = env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, format_simple_value(value)))
+            .collect::<Vec<_>>();
+        lines.push(format!("env = {}", vars.join(", ")));
+    }
+    if let Some(stdin) = args.get("stdin").and_then(Value::as_str) {
+        let stdin_lines = stdin.lines().collect::<Vec<_>>();
+        let mut shown = stdin_lines
+            .iter()
+            .take(MAX_STDIN_LINES)
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n");
+        if stdin_lines.len() > MAX_STDIN_LINES {
+            shown.push_str(&format!(
+                "\n… {} more lines",
+                stdin_lines.len() - MAX_STDIN_LINES
+            ));
+        }
+        lines.push(format!("stdin = ```\n{}\n```", shown));
+    }
+    Some(lines.join("\n"))
+}
+
 fn format_json_value(value: &Value) -> String {
     match value {
         Value::Object(obj) => {
@@ -712,4 +762,37 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_format_run_command_arguments() {
+        let tool_call = |name: &str, arguments: &str| ToolCall {
+            id: "test".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+
+        let formatted = format_run_command_arguments(&tool_call(
+            "run_command",
+            r#"{"command":"psql -f -","env":{"PGPASSWORD":"[REDACTED_SECRET:pw:ab12]","PGHOST":"db"},"stdin":"select 1;","shell":"bash","timeout_seconds":30}"#,
+        ));
+        assert_eq!(
+            formatted.as_deref(),
+            Some(
+                "command = psql -f -\nshell = bash\ntimeout_seconds = 30\nenv = PGHOST=db, PGPASSWORD=[REDACTED_SECRET:pw:ab12]\nstdin = ```\nselect 1;\n```"
+            )
+        );
+
+        let stdin = (1..=25)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let arguments = serde_json::json!({ "command": "cat", "stdin": stdin }).to_string();
+        let formatted = format_run_command_arguments(&tool_call("run_command", &arguments));
+        assert!(formatted.is_some_and(|f| f.ends_with("20\n… 5 more lines\n```")));
+
+        assert!(format_run_command_arguments(&tool_call("view", r#"{"path":"."}"#)).is_none());
+    }
 }
@@ -1,5 +1,6 @@
 pub mod bash_block;
 pub mod confirmation_dialog;
+pub mod diff;
 pub mod helper_block;
 pub mod helper_dropdown;
 pub mod hint_helper;
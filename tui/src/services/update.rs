@@ -1,7 +1,8 @@
-use crate::app::{AppState, InputEvent, LoadingType, OutputEvent};
+use crate::app::{AllowScope, AppState, InputEvent, LoadingType, OutputEvent};
 use crate::services::bash_block::{
     render_bash_block, render_bash_block_rejected, render_styled_block,
 };
+use crate::services::diff::FileDiff;
 use crate::services::helper_block::{
     push_error_message, push_help_message, push_memorize_message, push_status_message,
     push_styled_message, render_system_message,
@@ -18,7 +19,10 @@ use std::collections::HashMap;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
-use super::message::{extract_full_command_arguments, extract_truncated_command_arguments};
+use super::message::{
+    extract_full_command_arguments, extract_truncated_command_arguments,
+    format_run_command_arguments,
+};
 
 pub fn update(
     state: &mut AppState,
@@ -36,6 +40,8 @@ pub fn update(
                 if state.session_selected > 0 {
                     state.session_selected -= 1;
                 }
+            } else if state.is_dialog_open {
+                handle_dialog_up(state);
             } else if state.show_helper_dropdown
                 && !state.filtered_helpers.is_empty()
                 && state.input.starts_with('/')
@@ -50,6 +56,8 @@ pub fn update(
                 if state.session_selected + 1 < state.sessions.len() {
                     state.session_selected += 1;
                 }
+            } else if state.is_dialog_open {
+                handle_dialog_down(state);
             } else if state.show_helper_dropdown
                 && !state.filtered_helpers.is_empty()
                 && state.input.starts_with('/')
@@ -59,6 +67,8 @@ pub fn update(
                 handle_scroll_down(state, message_area_height, message_area_width);
             }
         }
+        InputEvent::DialogUp => handle_dialog_up(state),
+        InputEvent::DialogDown => handle_dialog_down(state),
         InputEvent::DropdownUp => handle_dropdown_up(state),
         InputEvent::DropdownDown => handle_dropdown_down(state),
         InputEvent::InputChanged(c) => handle_input_changed(state, c),
@@ -81,6 +91,14 @@ pub fn update(
         InputEvent::StreamToolResult(progress) => {
             handle_stream_tool_result(state, progress, terminal_size)
         }
+        InputEvent::ScrollUp if has_dialog_diff(state) => scroll_dialog_diff(state, -1),
+        InputEvent::ScrollDown if has_dialog_diff(state) => scroll_dialog_diff(state, 1),
+        InputEvent::PageUp if has_dialog_diff(state) => {
+            scroll_dialog_diff(state, -(DIALOG_DIFF_PAGE as isize))
+        }
+        InputEvent::PageDown if has_dialog_diff(state) => {
+            scroll_dialog_diff(state, DIALOG_DIFF_PAGE as isize)
+        }
         InputEvent::ScrollUp => handle_scroll_up(state),
         InputEvent::ScrollDown => {
             handle_scroll_down(state, message_area_height, message_area_width)
@@ -111,12 +129,30 @@ pub fn update(
         InputEvent::ToggleCursorVisible => state.cursor_visible = !state.cursor_visible,
         InputEvent::ShowConfirmationDialog(tool_call) => {
             state.dialog_command = Some(tool_call.clone());
-            let full_command = extract_full_command_arguments(&tool_call);
+            state.dialog_diff = None;
+            state.dialog_diff_scroll = 0;
+            // The diff shows the content, the block only needs the file
+            let full_command = match FileDiff::edited_path(&tool_call) {
+                Some(path) => {
+                    request_dialog_diff(&tool_call, shell_tx);
+                    path
+                }
+                None => format_run_command_arguments(&tool_call)
+                    .unwrap_or_else(|| extract_full_command_arguments(&tool_call)),
+            };
             let message_id =
                 render_bash_block(&tool_call, &full_command, false, state, terminal_size);
             state.pending_bash_message_id = Some(message_id);
+            state.dialog_selected = 0;
             state.is_dialog_open = true;
         }
+        // Only the diff of the tool call still in the dialog, as it is now
+        InputEvent::DialogDiffReady(tool_call, diff)
+            if state.dialog_command.as_ref() == Some(&tool_call) =>
+        {
+            state.dialog_diff = diff;
+            state.dialog_diff_scroll = 0;
+        }
         InputEvent::Loading(is_loading) => {
             state.loading = is_loading;
         }
@@ -284,6 +320,86 @@ fn handle_shell_mode(state: &mut AppState) {
 
 fn handle_tab(_state: &mut AppState) {}
 
+/// Number of options in the confirmation dialog: yes, always allow this, always allow the tool
+const DIALOG_OPTIONS: usize = 3;
+
+/// Lines the diff in the confirmation dialog moves on page up and page down
+const DIALOG_DIFF_PAGE: usize = 10;
+
+fn has_dialog_diff(state: &AppState) -> bool {
+    state.is_dialog_open && state.dialog_diff.is_some()
+}
+
+fn scroll_dialog_diff(state: &mut AppState, lines: isize) {
+    let max_scroll = state
+        .dialog_diff
+        .as_ref()
+        .map(|diff| diff.lines.len().saturating_sub(1))
+        .unwrap_or(0);
+    state.dialog_diff_scroll = state
+        .dialog_diff_scroll
+        .saturating_add_signed(lines)
+        .min(max_scroll);
+}
+
+/// Compute the diff of a file edit tool call on a blocking thread, it's sent back as
+/// `DialogDiffReady`
+fn request_dialog_diff(tool_call: &ToolCall, tx: &Sender<InputEvent>) {
+    let tool_call = tool_call.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let diff_tool_call = tool_call.clone();
+        let diff = tokio::task::spawn_blocking(move || FileDiff::from_tool_call(&diff_tool_call))
+            .await
+            .ok()
+            .flatten();
+        let _ = tx.send(InputEvent::DialogDiffReady(tool_call, diff)).await;
+    });
+}
+
+/// Replace the proposed content of the file edit in the confirmation dialog with `content`,
+/// the diff is updated once it's been computed again
+pub fn set_dialog_edit_content(state: &mut AppState, content: String, tx: &Sender<InputEvent>) {
+    let (Some(tool_call), Some(diff)) = (&mut state.dialog_command, &state.dialog_diff) else {
+        return;
+    };
+    let Ok(serde_json::Value::Object(mut arguments)) =
+        serde_json::from_str::<serde_json::Value>(&tool_call.function.arguments)
+    else {
+        return;
+    };
+    arguments.insert(
+        diff.editable_argument.to_string(),
+        serde_json::Value::String(content),
+    );
+    tool_call.function.arguments = serde_json::Value::Object(arguments).to_string();
+    request_dialog_diff(tool_call, tx);
+}
+
+/// The proposed content of the file edit in the confirmation dialog
+pub fn dialog_edit_content(state: &AppState) -> Option<String> {
+    let tool_call = state.dialog_command.as_ref()?;
+    let diff = state.dialog_diff.as_ref()?;
+    let arguments: serde_json::Value = serde_json::from_str(&tool_call.function.arguments).ok()?;
+    Some(
+        arguments
+            .get(diff.editable_argument)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    )
+}
+
+fn handle_dialog_up(state: &mut AppState) {
+    state.dialog_selected = state.dialog_selected.saturating_sub(1);
+}
+
+fn handle_dialog_down(state: &mut AppState) {
+    if state.dialog_selected + 1 < DIALOG_OPTIONS {
+        state.dialog_selected += 1;
+    }
+}
+
 fn handle_dropdown_up(state: &mut AppState) {
     if state.show_helper_dropdown
         && !state.filtered_helpers.is_empty()
@@ -309,6 +425,10 @@ fn handle_input_changed(state: &mut AppState, c: char) {
         state.show_shortcuts = !state.show_shortcuts;
         return;
     }
+    if c == 'e' && state.input.is_empty() && has_dialog_diff(state) {
+        state.dialog_edit_requested = true;
+        return;
+    }
     if c == '$' && (state.input.is_empty() || state.is_dialog_open) {
         state.input.clear();
         handle_shell_mode(state);
@@ -389,6 +509,7 @@ fn handle_esc(state: &mut AppState, output_tx: &Sender<OutputEvent>) {
         }
         state.is_dialog_open = false;
         state.dialog_command = None;
+        state.dialog_diff = None;
     } else if state.show_shell_mode {
         state.show_shell_mode = false;
         state.input.clear();
@@ -450,20 +571,18 @@ fn handle_input_submitted(
         state.is_dialog_open = false;
         state.input.clear();
         state.cursor_position = 0;
-        if state.dialog_selected == 0 {
-            if let Some(tool_call) = &state.dialog_command {
-                let _ = output_tx.try_send(OutputEvent::AcceptTool(tool_call.clone()));
-            }
-        } else {
-            // Clone dialog_command before mutating state
-            let tool_call_opt = state.dialog_command.clone();
-            if let Some(tool_call) = &tool_call_opt {
-                let truncated_command = extract_truncated_command_arguments(tool_call);
-                render_bash_block_rejected(&truncated_command, state);
-            }
+        if let Some(tool_call) = &state.dialog_command {
+            let event = match state.dialog_selected {
+                1 => OutputEvent::AlwaysAllowTool(tool_call.clone(), AllowScope::ToolCall),
+                2 => OutputEvent::AlwaysAllowTool(tool_call.clone(), AllowScope::Tool),
+                _ => OutputEvent::AcceptTool(tool_call.clone()),
+            };
+            let _ = output_tx.try_send(event);
         }
+        state.dialog_selected = 0;
 
         state.dialog_command = None;
+        state.dialog_diff = None;
     } else if state.show_helper_dropdown && !state.filtered_helpers.is_empty() {
         let selected = state.filtered_helpers[state.helper_selected];
 
@@ -485,6 +604,13 @@ fn handle_input_submitted(
                 state.show_helper_dropdown = false;
                 return;
             }
+            "/undo" => {
+                let _ = output_tx.try_send(OutputEvent::UndoLastEdit);
+                state.input.clear();
+                state.cursor_position = 0;
+                state.show_helper_dropdown = false;
+                return;
+            }
             "/help" => {
                 push_help_message(state);
                 state.input.clear();
@@ -612,12 +738,16 @@ fn handle_stream_tool_result(
 ) {
     let tool_call_id = progress.id;
     state.streaming_tool_result_id = Some(tool_call_id);
-    // 1. Update the buffer for this tool_call_id
-    state
+    // 1. Update the buffer for this tool_call_id, raw terminal output already has its newlines
+    let buffer = state
         .streaming_tool_results
         .entry(tool_call_id)
-        .or_default()
-        .push_str(&format!("{}\n", progress.message));
+        .or_default();
+    if progress.raw {
+        buffer.push_str(&progress.message);
+    } else {
+        buffer.push_str(&format!("{}\n", progress.message));
+    }
 
     // 2. Remove the old message with this id (if any)
     state.messages.retain(|m| m.id != tool_call_id);
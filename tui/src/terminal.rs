@@ -1,3 +1,6 @@
+use std::io;
+use std::process::Command;
+
 pub struct TerminalGuard;
 
 impl Drop for TerminalGuard {
@@ -6,3 +9,41 @@ impl Drop for TerminalGuard {
         let _ = crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen);
     }
 }
+
+/// Let the user edit `content` in $VISUAL, $EDITOR or vi, suspending the TUI meanwhile
+pub fn edit_in_external_editor(content: &str) -> io::Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args.next().unwrap_or("vi");
+
+    let path = std::env::temp_dir().join(format!("stakpak-edit-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&path, content)?;
+
+    crossterm::terminal::disable_raw_mode()?;
+    crossterm::execute!(io::stdout(), crossterm::terminal::LeaveAlternateScreen)?;
+    let status = Command::new(program).args(editor_args).arg(&path).status();
+    crossterm::terminal::enable_raw_mode()?;
+    crossterm::execute!(io::stdout(), crossterm::terminal::EnterAlternateScreen)?;
+
+    let edited = match status {
+        Ok(status) if status.success() => std::fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        ))),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("Failed to run {}: {}", program, e),
+        )),
+    };
+    let _ = std::fs::remove_file(&path);
+
+    // Editors end files with a newline, keep the content's own ending
+    let edited = edited?;
+    match edited.strip_suffix('\n') {
+        Some(stripped) if !content.ends_with('\n') => Ok(stripped.to_string()),
+        _ => Ok(edited),
+    }
+}